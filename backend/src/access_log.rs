@@ -0,0 +1,110 @@
+use std::{path::Path, time::Instant};
+
+use actix_web::{
+    body::{BodySize, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+};
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_subscriber::{
+    filter::filter_fn, fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter, Layer,
+};
+
+use crate::request_id::REQUEST_ID_HEADER;
+
+/// Target of the per-request events that only the access log file receives.
+const ACCESS_LOG_TARGET: &str = "access_log";
+
+/// How the stdout log is written, from `LOG_FORMAT`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// tracing's default human-readable lines.
+    Pretty,
+    /// One JSON object per line, for log aggregators.
+    Json,
+}
+
+/// Installs the stdout logger and, when `dir` is set, a daily-rotated JSON
+/// access log. The returned guard flushes the file writer when dropped, so
+/// it must live until shutdown.
+pub fn init_tracing(dir: Option<&Path>, format: LogFormat) -> Option<WorkerGuard> {
+    let not_access_log = || filter_fn(|meta| meta.target() != ACCESS_LOG_TARGET);
+    // Only one of these is set. JSON lines carry the enclosing spans' fields,
+    // so events logged while handling a request include its `request_id`.
+    let (pretty, json) = match format {
+        LogFormat::Pretty => {
+            let layer = fmt::layer()
+                .with_filter(EnvFilter::from_default_env())
+                .with_filter(not_access_log());
+            (Some(layer), None)
+        }
+        LogFormat::Json => {
+            let layer = fmt::layer()
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+                .with_filter(EnvFilter::from_default_env())
+                .with_filter(not_access_log());
+            (None, Some(layer))
+        }
+    };
+
+    // The non-blocking writer hands lines to a background thread and drops
+    // them if it falls behind, so a slow disk never stalls a request.
+    let (file, guard) = match dir {
+        Some(dir) => {
+            let (writer, guard) =
+                tracing_appender::non_blocking(tracing_appender::rolling::daily(dir, "access.log"));
+            let layer = fmt::layer()
+                .json()
+                .with_ansi(false)
+                .with_writer(writer)
+                .with_filter(filter_fn(|meta| meta.target() == ACCESS_LOG_TARGET));
+            (Some(layer), Some(guard))
+        }
+        None => (None, None),
+    };
+
+    tracing_subscriber::registry()
+        .with(pretty)
+        .with(json)
+        .with(file)
+        .init();
+    guard
+}
+
+/// Emits one access log event per request. It runs outside the request id
+/// middleware so the id is read back from the response header.
+pub async fn log_access(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let method = req.method().to_string();
+    let path = req.path().to_string();
+    let started = Instant::now();
+
+    let response = next.call(req).await?;
+
+    // Streamed bodies without a known length are logged without `bytes`.
+    let bytes = match response.response().body().size() {
+        BodySize::Sized(size) => Some(size),
+        BodySize::None | BodySize::Stream => None,
+    };
+    let request_id = response
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    tracing::info!(
+        target: ACCESS_LOG_TARGET,
+        method,
+        path,
+        status = response.status().as_u16(),
+        latency_ms = started.elapsed().as_millis() as u64,
+        bytes,
+        request_id,
+        "request"
+    );
+
+    Ok(response)
+}
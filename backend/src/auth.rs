@@ -0,0 +1,183 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::Method,
+    middleware::Next,
+    web::Data,
+    HttpRequest, ResponseError,
+};
+use subtle::ConstantTimeEq;
+
+use crate::{error::ApiError, session::SessionAuth};
+
+/// Header clients use to present the shared secret.
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Routes that stay reachable without a key or session so orchestrator probes
+/// and the login flow keep working. Paths are relative to the `/api` scope so
+/// they hold under any `BASE_PATH`.
+const PUBLIC_ROUTES: [&str; 4] = ["/health", "/ready", "/login", "/logout"];
+
+/// Admin routes check the key themselves with [`require_admin`], so
+/// `require_auth` lets them through and a caller without the key gets that
+/// 403 instead of a 401. Paths are relative to the `/api` scope.
+fn is_admin_route(method: &Method, path: &str) -> bool {
+    match *method {
+        Method::DELETE => path.starts_with("/videos/"),
+        Method::POST => {
+            path == "/reindex"
+                || path == "/videos/upload-url"
+                || path.starts_with("/videos/restore/")
+                || path.starts_with("/videos/multipart/")
+        }
+        _ => false,
+    }
+}
+
+/// The configured `API_KEY`; `None` leaves the API open for local development.
+pub struct ApiKey(pub Option<String>);
+
+fn keys_match(presented: &[u8], expected: &[u8]) -> bool {
+    presented.ct_eq(expected).into()
+}
+
+/// Admin routes that change the bucket need the API key itself: a browser
+/// session isn't enough, and without `API_KEY` they stay disabled entirely.
+pub fn require_admin(req: &HttpRequest) -> Result<(), ApiError> {
+    let expected = req
+        .app_data::<Data<ApiKey>>()
+        .and_then(|api_key| api_key.0.as_deref())
+        .ok_or_else(|| ApiError::forbidden("Admin routes require API_KEY to be configured"))?;
+    let authorized = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .is_some_and(|presented| keys_match(presented.as_bytes(), expected.as_bytes()));
+    if authorized {
+        Ok(())
+    } else {
+        Err(ApiError::forbidden("This route requires the API key"))
+    }
+}
+
+/// Rejects requests that present neither a matching `X-API-Key` header nor a
+/// valid session cookie. With neither configured the API stays open.
+pub async fn require_auth(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let expected = req
+        .app_data::<Data<ApiKey>>()
+        .and_then(|api_key| api_key.0.clone());
+    let session = req.app_data::<Data<SessionAuth>>().cloned();
+    let session = session.as_ref().and_then(|session| session.0.as_ref());
+
+    if expected.is_none() && session.is_none() {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+    let path = req.match_info().unprocessed();
+    if PUBLIC_ROUTES.contains(&path) || is_admin_route(req.method(), path) {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    let key_ok = expected.is_some_and(|expected| {
+        req.headers()
+            .get(API_KEY_HEADER)
+            .is_some_and(|presented| keys_match(presented.as_bytes(), expected.as_bytes()))
+    });
+    let session_ok = session.is_some_and(|session| session.is_authenticated(req.request()));
+
+    if key_ok || session_ok {
+        next.call(req).await.map(ServiceResponse::map_into_left_body)
+    } else {
+        let error = ApiError::unauthorized("Missing or invalid API key or session");
+        Ok(req.into_response(error.error_response()).map_into_right_body())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        http::StatusCode,
+        middleware::from_fn,
+        test::{call_service, init_service, TestRequest},
+        web, App, HttpResponse,
+    };
+
+    use super::*;
+
+    async fn delete(req: HttpRequest) -> Result<HttpResponse, ApiError> {
+        require_admin(&req)?;
+        Ok(HttpResponse::NoContent().finish())
+    }
+
+    async fn status(api_key: Option<&str>, path: &str, presented: Option<&str>) -> StatusCode {
+        call(api_key, Method::GET, path, presented).await
+    }
+
+    async fn call(
+        api_key: Option<&str>,
+        method: Method,
+        path: &str,
+        presented: Option<&str>,
+    ) -> StatusCode {
+        let app = init_service(
+            App::new()
+                .app_data(Data::new(ApiKey(api_key.map(str::to_string))))
+                .service(
+                    web::scope("/api")
+                        .wrap(from_fn(require_auth))
+                        .route("/videos", web::get().to(HttpResponse::Ok))
+                        .route("/health", web::get().to(HttpResponse::Ok))
+                        .route("/videos/{key:.*}", web::delete().to(delete)),
+                ),
+        )
+        .await;
+        let mut req = TestRequest::default().method(method).uri(path);
+        if let Some(presented) = presented {
+            req = req.insert_header((API_KEY_HEADER, presented));
+        }
+        call_service(&app, req.to_request()).await.status()
+    }
+
+    #[actix_web::test]
+    async fn matching_key_is_let_through() {
+        assert_eq!(status(Some("secret"), "/api/videos", Some("secret")).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn missing_or_wrong_key_is_rejected() {
+        assert_eq!(
+            status(Some("secret"), "/api/videos", None).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(Some("secret"), "/api/videos", Some("secreT")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(Some("secret"), "/api/videos", Some("")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[actix_web::test]
+    async fn probes_and_unconfigured_keys_stay_open() {
+        assert_eq!(status(Some("secret"), "/api/health", None).await, StatusCode::OK);
+        assert_eq!(status(None, "/api/videos", None).await, StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn admin_routes_answer_403_without_the_key() {
+        let uri = "/api/videos/a.mp4";
+        assert_eq!(call(Some("secret"), Method::DELETE, uri, None).await, StatusCode::FORBIDDEN);
+        assert_eq!(
+            call(Some("secret"), Method::DELETE, uri, Some("secreT")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            call(Some("secret"), Method::DELETE, uri, Some("secret")).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(call(None, Method::DELETE, uri, None).await, StatusCode::FORBIDDEN);
+    }
+}
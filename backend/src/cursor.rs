@@ -0,0 +1,98 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ApiError, ListQuery};
+
+/// What a listing `cursor` carries: the S3 continuation token plus every
+/// query field that shapes a `nativeOrder` listing, so infinite-scroll
+/// clients only send `cursor=` to fetch the next chunk. Sorting is left out
+/// because native order ignores it.
+#[derive(Serialize, Deserialize)]
+#[allow(non_snake_case)]
+pub struct ListCursor {
+    token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pageSize: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    prefix: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    recursive: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    search: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bucket: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modifiedAfter: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modifiedBefore: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    minSize: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maxSize: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ext: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hideEmptyFolders: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rawPrefix: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    withTags: Option<bool>,
+}
+
+impl ListCursor {
+    pub fn new(query: &ListQuery, token: String) -> Self {
+        Self {
+            token,
+            pageSize: query.pageSize,
+            prefix: query.prefix.clone(),
+            recursive: query.recursive,
+            search: query.search.clone(),
+            bucket: query.bucket.clone(),
+            modifiedAfter: query.modifiedAfter.clone(),
+            modifiedBefore: query.modifiedBefore.clone(),
+            minSize: query.minSize,
+            maxSize: query.maxSize,
+            ext: query.ext.clone(),
+            hideEmptyFolders: query.hideEmptyFolders,
+            rawPrefix: query.rawPrefix,
+            withTags: query.withTags,
+        }
+    }
+
+    /// URL-safe base64 of the JSON form. Not signed: everything inside is
+    /// validated again on decode, exactly as if it came from the query.
+    pub fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).unwrap_or_default();
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, ApiError> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or_else(|| ApiError::bad_request("cursor is not a valid listing cursor"))
+    }
+
+    /// The query this cursor stands for, resuming at its token.
+    pub fn into_query(self) -> ListQuery {
+        ListQuery {
+            pageSize: self.pageSize,
+            prefix: self.prefix,
+            recursive: self.recursive,
+            search: self.search,
+            bucket: self.bucket,
+            modifiedAfter: self.modifiedAfter,
+            modifiedBefore: self.modifiedBefore,
+            minSize: self.minSize,
+            maxSize: self.maxSize,
+            nativeOrder: Some(true),
+            continuationToken: Some(self.token),
+            ext: self.ext,
+            hideEmptyFolders: self.hideEmptyFolders,
+            rawPrefix: self.rawPrefix,
+            withTags: self.withTags,
+            ..ListQuery::default()
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt;
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, StatusCode},
+    middleware::Next,
+    web::Data,
+    HttpResponse, ResponseError,
+};
+use aws_sdk_s3::{
+    error::{ProvideErrorMetadata, SdkError},
+    operation::RequestId,
+};
+use serde::Serialize;
+
+use crate::AppState;
+
+/// Error returned by API handlers, rendered as
+/// `{ "error": { "code": "...", "message": "..." } }`.
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    retry_after_seconds: Option<u64>,
+    /// What S3 said about the failure, shown only with `EXPOSE_S3_ERRORS`.
+    s3: Option<S3ErrorDetail>,
+}
+
+#[derive(Debug, Serialize)]
+struct S3ErrorDetail {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+    #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetail<'a>,
+}
+
+#[derive(Serialize)]
+struct ErrorDetail<'a> {
+    code: &'a str,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    s3: Option<&'a S3ErrorDetail>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            code,
+            message: message.into(),
+            retry_after_seconds: None,
+            s3: None,
+        }
+    }
+
+    /// Adds a `Retry-After` header telling clients when to try again.
+    pub fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after_seconds = Some(seconds);
+        self
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "unauthorized", message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "forbidden", message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    /// For objects in an archive storage class that must be restored first.
+    pub fn archived(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, "object_archived", message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal_error", message)
+    }
+
+    /// Classifies an S3 SDK error by its error code, falling back to the raw
+    /// HTTP status for responses without a body (e.g. `HeadObject`).
+    pub fn from_s3<E>(context: &str, err: SdkError<E>) -> Self
+    where
+        E: ProvideErrorMetadata + std::error::Error + 'static,
+    {
+        let http_status = err.raw_response().map(|response| response.status().as_u16());
+        let message = format!("{context}: {err}");
+        let s3 = S3ErrorDetail {
+            code: err.code().map(str::to_string),
+            request_id: err.request_id().map(str::to_string),
+        };
+        let s3 = (s3.code.is_some() || s3.request_id.is_some()).then_some(s3);
+
+        if is_timeout(&err) {
+            return Self::new(StatusCode::GATEWAY_TIMEOUT, "gateway_timeout", message);
+        }
+
+        let error = match (err.code(), http_status) {
+            (Some("NoSuchKey" | "NotFound" | "NoSuchBucket"), _) | (None, Some(404)) => {
+                Self::not_found(message)
+            }
+            (Some("InvalidObjectState"), _) => Self::archived(format!(
+                "{message} (the object is archived and must be restored first)"
+            )),
+            (
+                Some(
+                    "AccessDenied"
+                    | "AllAccessDisabled"
+                    | "InvalidAccessKeyId"
+                    | "SignatureDoesNotMatch",
+                ),
+                _,
+            )
+            | (None, Some(403)) => Self::forbidden(message),
+            _ => Self::internal(message),
+        };
+        Self { s3, ..error }
+    }
+
+    fn render(&self, expose_s3: bool) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status);
+        if let Some(seconds) = self.retry_after_seconds {
+            response.insert_header((header::RETRY_AFTER, seconds));
+        }
+        response.json(ErrorBody {
+            error: ErrorDetail {
+                code: self.code,
+                message: &self.message,
+                s3: self.s3.as_ref().filter(|_| expose_s3),
+            },
+        })
+    }
+}
+
+/// Whether the SDK gave up because a connect or operation timeout fired.
+pub fn is_timeout<E>(err: &SdkError<E>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) => true,
+        SdkError::DispatchFailure(failure) => failure.is_timeout(),
+        _ => false,
+    }
+}
+
+/// Whether the request never reached S3: the connection failed or timed out.
+/// These are the errors a mirror endpoint can help with.
+pub fn is_connection_failure<E>(err: &SdkError<E>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) => true,
+        SdkError::DispatchFailure(failure) => failure.is_io() || failure.is_timeout(),
+        _ => false,
+    }
+}
+
+/// Whether S3 rejected a `Range` that starts beyond the end of the object.
+pub fn is_invalid_range<E: ProvideErrorMetadata>(err: &SdkError<E>) -> bool {
+    err.code() == Some("InvalidRange")
+        || err
+            .raw_response()
+            .is_some_and(|response| response.status().as_u16() == 416)
+}
+
+/// Whether S3 answered a conditional GET with 304 Not Modified.
+pub fn is_not_modified<E>(err: &SdkError<E>) -> bool {
+    err.raw_response()
+        .is_some_and(|response| response.status().as_u16() == 304)
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.render(false)
+    }
+}
+
+/// With `EXPOSE_S3_ERRORS` on, re-renders errors caused by S3 with the S3
+/// error code and request id under `error.s3`. They are left out by default
+/// because they reveal how the bucket is set up.
+pub async fn expose_s3_errors(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let enabled = req
+        .app_data::<Data<AppState>>()
+        .is_some_and(|state| state.expose_s3_errors);
+    let response = next.call(req).await?;
+    if !enabled {
+        return Ok(response.map_into_left_body());
+    }
+
+    let detailed = response
+        .response()
+        .error()
+        .and_then(|err| err.as_error::<ApiError>())
+        .filter(|err| err.s3.is_some())
+        .map(|err| err.render(true));
+    Ok(match detailed {
+        Some(detailed) => response.into_response(detailed).map_into_right_body(),
+        None => response.map_into_left_body(),
+    })
+}
@@ -0,0 +1,185 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    pin::pin,
+    sync::Mutex,
+    time::Duration,
+};
+
+use actix_web::{
+    get,
+    web::{self, Data, Query},
+    HttpRequest, HttpResponse,
+};
+use actix_ws::Message;
+use futures_util::{
+    future::{select, Either},
+    StreamExt,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{list_all_objects, validate_key, AppState};
+
+/// Events buffered per prefix before a slow subscriber starts missing some.
+const CHANNEL_CAPACITY: usize = 16;
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    prefix: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ChangeEvent<'a> {
+    prefix: &'a str,
+    added: Vec<&'a str>,
+    removed: Vec<&'a str>,
+}
+
+struct Topic {
+    sender: broadcast::Sender<String>,
+    /// Video keys seen on the last poll; `None` until the first one completes.
+    snapshot: Option<BTreeSet<String>>,
+}
+
+/// Tracks which prefixes have live `/api/events` subscribers and the last
+/// listing seen for each, so only watched prefixes are ever re-listed.
+pub struct EventHub {
+    topics: Mutex<HashMap<String, Topic>>,
+}
+
+impl EventHub {
+    pub fn new() -> Self {
+        Self {
+            topics: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn subscribe(&self, prefix: &str) -> broadcast::Receiver<String> {
+        let mut topics = self.topics.lock().unwrap_or_else(|p| p.into_inner());
+        topics
+            .entry(prefix.to_string())
+            .or_insert_with(|| Topic {
+                sender: broadcast::channel(CHANNEL_CAPACITY).0,
+                snapshot: None,
+            })
+            .sender
+            .subscribe()
+    }
+
+    /// Drops prefixes nobody is listening to and returns the rest.
+    fn watched_prefixes(&self) -> Vec<String> {
+        let mut topics = self.topics.lock().unwrap_or_else(|p| p.into_inner());
+        topics.retain(|_, topic| topic.sender.receiver_count() > 0);
+        topics.keys().cloned().collect()
+    }
+
+    /// Stores the new listing and notifies subscribers of any difference.
+    fn publish(&self, prefix: &str, keys: BTreeSet<String>) {
+        let mut topics = self.topics.lock().unwrap_or_else(|p| p.into_inner());
+        let Some(topic) = topics.get_mut(prefix) else {
+            return;
+        };
+
+        if let Some(previous) = &topic.snapshot {
+            let event = ChangeEvent {
+                prefix,
+                added: keys.difference(previous).map(String::as_str).collect(),
+                removed: previous.difference(&keys).map(String::as_str).collect(),
+            };
+            if (!event.added.is_empty() || !event.removed.is_empty())
+                && let Ok(message) = serde_json::to_string(&event)
+            {
+                // Sending only fails when every receiver is gone.
+                let _ = topic.sender.send(message);
+            }
+        }
+        topic.snapshot = Some(keys);
+    }
+}
+
+/// Re-lists every watched prefix each `interval` and publishes the diff.
+/// Listings bypass the cache so changes show up within one interval.
+pub async fn poll_changes(state: Data<AppState>, hub: Data<EventHub>, interval: Duration) {
+    let mut ticker = actix_web::rt::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        for prefix in hub.watched_prefixes() {
+            let s3_prefix = state.s3_key(&prefix);
+            let listing = list_all_objects(
+                &state.s3,
+                &state.retry,
+                &state.bucket,
+                &s3_prefix,
+                Some(&state.delimiter),
+                state.max_list_objects,
+                state.request_payer.as_ref(),
+            )
+            .await;
+            match listing {
+                Ok(listing) => {
+                    let keys = listing
+                        .objects
+                        .iter()
+                        .filter_map(|item| item.key())
+                        .map(|key| state.client_key(key))
+                        .filter(|key| {
+                            state.is_video_key(key)
+                                && !state.is_hidden(key)
+                                && state.key_filter.allows(key)
+                        })
+                        .map(str::to_string)
+                        .collect();
+                    hub.publish(&prefix, keys);
+                }
+                Err(err) => {
+                    state.metrics.record_s3_failure("list_objects_v2");
+                    tracing::warn!("Failed to poll {prefix:?} for changes: {err}");
+                }
+            }
+        }
+    }
+}
+
+/// Upgrades to a WebSocket that receives a JSON message whenever videos are
+/// added to or removed from `prefix`.
+#[get("/events")]
+pub async fn events(
+    req: HttpRequest,
+    body: web::Payload,
+    state: Data<AppState>,
+    hub: Data<EventHub>,
+    query: Query<EventsQuery>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let prefix = query.prefix.clone().unwrap_or_default();
+    validate_key(&prefix, &state.delimiter)?;
+
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+    let mut changes = hub.subscribe(&prefix);
+
+    // The receiver lives in this task, so the prefix stops being polled once
+    // the client disconnects and the task ends.
+    actix_web::rt::spawn(async move {
+        loop {
+            let next_change = pin!(changes.recv());
+            match select(next_change, messages.next()).await {
+                Either::Left((Ok(message), _)) => {
+                    if session.text(message).await.is_err() {
+                        return;
+                    }
+                }
+                Either::Left((Err(RecvError::Lagged(_)), _)) => {}
+                Either::Left((Err(RecvError::Closed), _)) => break,
+                Either::Right((Some(Ok(Message::Ping(bytes))), _)) => {
+                    if session.pong(&bytes).await.is_err() {
+                        return;
+                    }
+                }
+                Either::Right((Some(Ok(Message::Close(_)) | Err(_)) | None, _)) => break,
+                Either::Right((Some(Ok(_)), _)) => {}
+            }
+        }
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
@@ -0,0 +1,67 @@
+use std::fmt::Write;
+
+use actix_web::{
+    get,
+    http::header,
+    web::{Data, Path},
+    HttpResponse,
+};
+
+use crate::{decode_key, error::ApiError, head_object, stream_url, AppState};
+
+/// Renders a media playlist of `#EXT-X-BYTERANGE` segments over the whole
+/// object. Without probing the container the segment size is derived from an
+/// assumed bitrate, so durations are estimates rather than exact.
+fn render_playlist(url: &str, size: u64, segment_seconds: u64, bitrate_kbps: u64) -> String {
+    // 1 kbit/s is 125 bytes/s.
+    let segment_bytes = bitrate_kbps
+        .saturating_mul(125)
+        .saturating_mul(segment_seconds)
+        .max(1);
+    let mut playlist = String::new();
+    let _ = writeln!(playlist, "#EXTM3U");
+    let _ = writeln!(playlist, "#EXT-X-VERSION:4");
+    let _ = writeln!(playlist, "#EXT-X-TARGETDURATION:{segment_seconds}");
+    let _ = writeln!(playlist, "#EXT-X-MEDIA-SEQUENCE:0");
+    let _ = writeln!(playlist, "#EXT-X-PLAYLIST-TYPE:VOD");
+
+    let mut offset = 0;
+    while offset < size {
+        let length = segment_bytes.min(size - offset);
+        let duration = segment_seconds as f64 * length as f64 / segment_bytes as f64;
+        let _ = writeln!(playlist, "#EXTINF:{duration:.3},");
+        let _ = writeln!(playlist, "#EXT-X-BYTERANGE:{length}@{offset}");
+        let _ = writeln!(playlist, "{url}");
+        offset += length;
+    }
+
+    let _ = writeln!(playlist, "#EXT-X-ENDLIST");
+    playlist
+}
+
+#[get("/videos/hls/{key:.*}/playlist.m3u8")]
+pub async fn hls_playlist(
+    state: Data<AppState>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let key = decode_key(&path.into_inner(), &state.delimiter)?;
+
+    let head = head_object(&state, &key).await?;
+
+    let size = head
+        .content_length()
+        .and_then(|len| u64::try_from(len).ok())
+        .filter(|len| *len > 0)
+        .ok_or_else(|| ApiError::bad_request("Object size is unknown or empty"))?;
+
+    let playlist = render_playlist(
+        &stream_url(&state.base_path, None, &key),
+        size,
+        state.hls_segment_seconds,
+        state.hls_assumed_bitrate_kbps,
+    );
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "application/vnd.apple.mpegurl"))
+        .body(playlist))
+}
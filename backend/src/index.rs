@@ -0,0 +1,147 @@
+use std::{collections::HashMap, pin::pin, sync::RwLock, time::Duration};
+
+use actix_web::{post, web::Data, HttpRequest, HttpResponse};
+use aws_sdk_s3::types::{CommonPrefix, Object};
+use futures_util::future::select;
+use tokio::sync::Notify;
+
+use crate::{auth, error::ApiError, list_all_objects, AppState, Listing};
+
+/// Every object under `KEY_PREFIX` in each allowed bucket, rebuilt in the
+/// background so `INDEX_MODE` listings never wait on S3.
+pub struct ObjectIndex {
+    /// Full recursive listings by bucket, sorted by key as S3 returns them.
+    buckets: RwLock<HashMap<String, IndexedBucket>>,
+    rebuild: Notify,
+}
+
+struct IndexedBucket {
+    objects: Vec<Object>,
+    /// `MAX_LIST_OBJECTS` stopped the rebuild before the last key.
+    truncated: bool,
+}
+
+impl ObjectIndex {
+    pub fn new() -> Self {
+        Self {
+            buckets: RwLock::new(HashMap::new()),
+            rebuild: Notify::new(),
+        }
+    }
+
+    /// Answers a listing the same way `list_all_objects` would, or `None`
+    /// until the bucket has been indexed once.
+    pub fn listing(
+        &self,
+        bucket: &str,
+        s3_prefix: &str,
+        delimiter: Option<&str>,
+    ) -> Option<Listing> {
+        let buckets = self.buckets.read().unwrap_or_else(|p| p.into_inner());
+        let indexed = buckets.get(bucket)?;
+        let objects = &indexed.objects;
+
+        // Keys sharing a prefix are contiguous in sorted order.
+        let start = objects.partition_point(|item| item.key().unwrap_or_default() < s3_prefix);
+        let matching = objects[start..]
+            .iter()
+            .take_while(|item| item.key().is_some_and(|key| key.starts_with(s3_prefix)));
+
+        // A prefix can only be missing keys if nothing past it made the index.
+        let last_key = objects.last().and_then(Object::key).unwrap_or_default();
+        let mut listing = Listing {
+            objects: Vec::new(),
+            common_prefixes: Vec::new(),
+            truncated: indexed.truncated
+                && (last_key.starts_with(s3_prefix) || last_key < s3_prefix),
+        };
+        for item in matching {
+            let key = item.key().unwrap_or_default();
+            let split = delimiter.and_then(|delimiter| {
+                let at = key[s3_prefix.len()..].find(delimiter)?;
+                Some(s3_prefix.len() + at + delimiter.len())
+            });
+            match split {
+                Some(end) => {
+                    let folder = &key[..end];
+                    let last = listing.common_prefixes.last().and_then(CommonPrefix::prefix);
+                    if last != Some(folder) {
+                        listing
+                            .common_prefixes
+                            .push(CommonPrefix::builder().prefix(folder).build());
+                    }
+                }
+                None => listing.objects.push(item.clone()),
+            }
+        }
+        Some(listing)
+    }
+
+    /// Wakes `refresh_index` for a rebuild ahead of its next tick.
+    pub fn request_rebuild(&self) {
+        self.rebuild.notify_one();
+    }
+
+    fn replace(&self, bucket: &str, listing: Listing) {
+        let mut buckets = self.buckets.write().unwrap_or_else(|p| p.into_inner());
+        buckets.insert(
+            bucket.to_string(),
+            IndexedBucket {
+                objects: listing.objects,
+                truncated: listing.truncated,
+            },
+        );
+    }
+}
+
+/// Rebuilds the index every `interval`, or sooner when `/api/reindex` asks.
+/// A bucket that fails to list keeps its previous index.
+pub async fn refresh_index(state: Data<AppState>, interval: Duration) {
+    let Some(index) = state.index.clone() else {
+        return;
+    };
+    let mut ticker = actix_web::rt::time::interval(interval);
+    loop {
+        select(pin!(ticker.tick()), pin!(index.rebuild.notified())).await;
+        for bucket in &state.allowed_buckets {
+            let listing = list_all_objects(
+                &state.s3,
+                &state.retry,
+                bucket,
+                &state.key_prefix,
+                None,
+                state.max_list_objects,
+                state.request_payer.as_ref(),
+            )
+            .await;
+            match listing {
+                Ok(listing) => {
+                    tracing::info!(
+                        bucket,
+                        objects = listing.objects.len(),
+                        truncated = listing.truncated,
+                        "Rebuilt listing index"
+                    );
+                    index.replace(bucket, listing);
+                }
+                Err(err) => {
+                    state.metrics.record_s3_failure("list_objects_v2");
+                    tracing::warn!("Failed to index bucket {bucket:?}: {err}");
+                }
+            }
+        }
+    }
+}
+
+/// Starts an index rebuild without waiting for it to finish. A rebuild lists
+/// every bucket in full, so like the other admin routes it needs the API key.
+#[post("/reindex")]
+pub async fn reindex(req: HttpRequest, state: Data<AppState>) -> Result<HttpResponse, ApiError> {
+    auth::require_admin(&req)?;
+    let index = state
+        .index
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("INDEX_MODE is not enabled"))?;
+    index.request_rebuild();
+    Ok(HttpResponse::Accepted().finish())
+}
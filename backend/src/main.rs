@@ -1,54 +1,417 @@
-use std::{env, time::Duration};
+mod access_log;
+mod auth;
+mod cache;
+mod cursor;
+mod error;
+mod events;
+mod hls;
+mod index;
+mod maintenance;
+mod metrics;
+#[cfg(test)]
+mod mock_s3;
+mod probe;
+mod rate_limit;
+mod request_id;
+mod restore;
+mod retry;
+mod server_timing;
+mod session;
+mod sprite;
+mod subtitles;
+mod thumbnail;
+mod throttle;
+mod tls;
+mod upload;
 
-use actix_files::Files;
+use std::{
+    backtrace::Backtrace,
+    cmp::Ordering,
+    collections::HashMap,
+    env,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    pin::pin,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use actix_cors::Cors;
+use actix_files::{Files, NamedFile};
 use actix_web::{
+    delete,
+    dev::{fn_service, ServiceRequest, ServiceResponse},
     get,
-    http::header,
-    middleware::Logger,
-    web::{self, Data, Path, Query},
-    App, HttpResponse, HttpServer, Responder,
+    post,
+    route,
+    http::{
+        header::{self, EntityTag, Header, IfNoneMatch},
+        KeepAlive, Method, StatusCode, Uri,
+    },
+    middleware::{from_fn, Compress, Logger},
+    web::{self, Bytes, Data, Path, Query},
+    App, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError,
 };
 use anyhow::{Context, Result};
 use aws_credential_types::Credentials;
-use aws_sdk_s3::{presigning::PresigningConfig, types::CommonPrefix, Client};
+use aws_sdk_s3::{
+    config::{retry::RetryConfig, timeout::TimeoutConfig},
+    error::SdkError,
+    operation::head_bucket::{HeadBucketError, HeadBucketOutput},
+    operation::head_object::HeadObjectOutput,
+    operation::list_objects_v2::ListObjectsV2Error,
+    presigning::PresigningConfig,
+    primitives::{ByteStream, ByteStreamError, DateTime, DateTimeFormat},
+    types::{CommonPrefix, Object, RequestPayer, StorageClass},
+    Client,
+};
+use access_log::LogFormat;
+use auth::ApiKey;
 use aws_types::region::Region;
+use cache::TtlCache;
+use cursor::ListCursor;
+use error::ApiError;
+use events::EventHub;
+use index::ObjectIndex;
+use maintenance::Maintenance;
+use metrics::Metrics;
+use probe::{probe_media, MediaInfo, PROBE_CACHE_MAX_ENTRIES, PROBE_CACHE_TTL};
+use rate_limit::{RateLimit, RateLimiter};
+use retry::RetryPolicy;
+use session::{SessionAuth, SessionConfig};
+use subtitles::SubtitleTrack;
+use tls::TlsPaths;
+use futures_util::{
+    future::{self, Either},
+    stream, Stream, StreamExt, TryStreamExt,
+};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
-use tracing_subscriber::EnvFilter;
+use tokio::sync::{mpsc, Semaphore};
+use tracing::Instrument;
 
 #[derive(Clone)]
 struct AppState {
     s3: Client,
+    /// Client for `AWS_S3_ENDPOINT_URL_FALLBACK`, used by `with_failover`.
+    s3_fallback: Option<Client>,
+    /// Bucket used when a request doesn't name one.
     bucket: String,
+    /// Every bucket requests may select, including the default.
+    allowed_buckets: Vec<String>,
+    /// Hidden prefix every key lives under; clients only see keys below it.
+    key_prefix: String,
+    /// Separator that splits keys into folders, `/` unless `DELIMITER` says
+    /// otherwise.
+    delimiter: String,
+    /// `false` with `FOLDERS_ENABLED=false`: every listing is flat and
+    /// `folders` always empty.
+    folders_enabled: bool,
+    /// `Requester` for requester-pays buckets, sent on every read and presign.
+    request_payer: Option<RequestPayer>,
+    /// Prepended to generated links so they work behind a path-based proxy.
+    base_path: String,
+    /// `404.html` in the static dir, served for unmatched non-API GETs when
+    /// `NOT_FOUND_PAGE` is on.
+    not_found_page: Option<PathBuf>,
+    /// `HEALTH_BODY`, sent as-is by the liveness route when set.
+    health_body: Option<String>,
+    presign_expiry_seconds: u64,
+    /// `PRESIGN_EXPIRY_OVERRIDES` as `(prefix, seconds)`, longest prefix
+    /// first so the first match is the most specific.
+    presign_expiry_overrides: Vec<(String, u64)>,
+    /// Turn a failed pre-redirect HEAD into an error, so missing keys get a clean 404.
+    verify_before_presign: bool,
+    /// Lifetime of presigned upload URLs, kept short since they grant writes.
+    upload_url_expiry_seconds: u64,
+    /// `Cache-Control` for listings and metadata; `None` sends no header.
+    list_cache_control: Option<String>,
+    metadata_cache_control: Option<String>,
+    /// Used when a listing asks for no `pageSize` (or zero).
+    default_page_size: usize,
+    /// Larger `pageSize` requests are clamped to this.
+    max_page_size: usize,
+    /// Stops any listing after this many objects so huge prefixes can't
+    /// exhaust memory; such listings are flagged `truncated`.
+    max_list_objects: Option<usize>,
+    video_extensions: Vec<String>,
+    /// Keys hidden from every listing.
+    key_filter: KeyFilter,
+    /// `TITLE_STRIP_REGEX`: matches removed from display titles.
+    title_strip: Option<Regex>,
+    /// Hide keys and folders with a path segment starting with `.`.
+    hide_dotfiles: bool,
+    stream_mode: StreamMode,
+    /// Serve a MIME type guessed from the key instead of the stored Content-Type.
+    override_content_type: bool,
+    /// Per-connection cap for proxied bodies; `None` streams at full speed.
+    stream_max_bytes_per_sec: Option<u64>,
+    /// S3 chunks read ahead of the client for proxied bodies; `None` reads
+    /// each chunk only when the client asks for it.
+    stream_prefetch_chunks: Option<usize>,
+    /// Caps simultaneous proxied bodies; `None` leaves them unlimited.
+    stream_permits: Option<Arc<Semaphore>>,
+    metrics: Data<Metrics>,
+    list_cache: Arc<TtlCache<Arc<Listing>>>,
+    /// Background-built listing of every object, set when `INDEX_MODE` is on.
+    index: Option<Arc<ObjectIndex>>,
+    thumbnail_dir: PathBuf,
+    /// Advertise `thumbnailUrl` on listed videos; off where ffmpeg is missing.
+    thumbnails_enabled: bool,
+    ffmpeg_path: String,
+    /// Caps simultaneous thumbnail and sprite jobs, each of which runs ffmpeg.
+    ffmpeg_permits: Arc<Semaphore>,
+    ffprobe_path: String,
+    /// Duration and resolution by key and ETag, including empty results for
+    /// files ffprobe couldn't read.
+    probe_cache: Arc<TtlCache<MediaInfo>>,
+    hls_segment_seconds: u64,
+    hls_assumed_bitrate_kbps: u64,
+    retry: RetryPolicy,
+    /// S3 calls slower than this are logged at warn level; `None` disables it.
+    s3_slow_log: Option<Duration>,
+    /// Send `Server-Timing` on `/api` responses; off by default since it
+    /// reveals backend latency.
+    server_timing: bool,
+    /// Include S3's error code and request id in API error bodies.
+    expose_s3_errors: bool,
+}
+
+/// How `/videos/stream` hands the object to the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StreamMode {
+    /// Redirect to a presigned S3 URL so the browser fetches directly from S3.
+    Redirect,
+    /// Fetch from S3 on the server and relay the bytes, forwarding `Range`.
+    Proxy,
+}
+
+/// Origins allowed to call `/api` from a browser on another origin.
+#[derive(Debug, Clone)]
+enum AllowedOrigins {
+    /// No CORS headers are sent, so only same-origin pages can read responses.
+    SameOrigin,
+    /// `ALLOWED_ORIGINS=*`: any origin is echoed back.
+    Any,
+    /// An explicit comma-separated list of origins such as `https://app.example.com`.
+    List(Vec<String>),
+}
+
+/// `KEY_ALLOW_REGEX` and `KEY_DENY_REGEX`, matched against whole keys as
+/// clients see them (below `KEY_PREFIX`). Deny wins over allow.
+#[derive(Debug, Clone, Default)]
+struct KeyFilter {
+    allow: Option<Regex>,
+    deny: Option<Regex>,
+}
+
+impl KeyFilter {
+    fn allows(&self, key: &str) -> bool {
+        !self.deny.as_ref().is_some_and(|deny| deny.is_match(key))
+            && self.allow.as_ref().is_none_or(|allow| allow.is_match(key))
+    }
 }
 
 #[derive(Debug, Clone)]
 struct AppConfig {
-    port: u16,
+    /// `BIND_ADDRESS` and `PORT` combined.
+    bind_addr: SocketAddr,
     static_dir: String,
+    /// Serve `index.html` for unknown non-API paths so client-side routes
+    /// survive a refresh.
+    spa_fallback: bool,
+    /// Serve `404.html` from the static dir for unmatched non-API GETs.
+    not_found_page: bool,
+    /// Path the whole app is mounted under, `/media` form or empty.
+    base_path: String,
+    /// Where the liveness route answers, below `base_path`.
+    health_path: String,
+    /// Exact plain-text liveness body; the JSON status when unset.
+    health_body: Option<String>,
     aws_region: String,
-    aws_access_key_id: String,
-    aws_secret_access_key: String,
+    /// Region the S3 client signs for, when it differs from `aws_region`.
+    aws_s3_region: Option<String>,
+    /// Static keys; when absent the SDK's default credential chain is used.
+    aws_access_key_id: Option<String>,
+    aws_secret_access_key: Option<String>,
+    /// Session token accompanying temporary (STS-issued) static keys.
+    aws_session_token: Option<String>,
     aws_s3_endpoint_url: Option<String>,
+    /// Mirror tried when the primary endpoint can't be reached.
+    aws_s3_endpoint_url_fallback: Option<String>,
     aws_s3_bucket_name: String,
+    aws_s3_bucket_names: Vec<String>,
     aws_s3_force_path_style: bool,
+    requester_pays: bool,
+    key_prefix: String,
+    delimiter: String,
+    /// Split listings into folders at `delimiter`; off lists every key below
+    /// the prefix as one flat list without asking S3 for common prefixes.
+    folders_enabled: bool,
+    presign_expiry_seconds: u64,
+    presign_expiry_overrides: Vec<(String, u64)>,
+    verify_before_presign: bool,
+    upload_url_expiry_seconds: u64,
+    list_cache_control: Option<String>,
+    metadata_cache_control: Option<String>,
+    default_page_size: usize,
+    max_page_size: usize,
+    max_list_objects: Option<usize>,
+    video_extensions: Vec<String>,
+    key_filter: KeyFilter,
+    title_strip: Option<Regex>,
+    hide_dotfiles: bool,
+    stream_mode: StreamMode,
+    override_content_type: bool,
+    stream_max_bytes_per_sec: Option<u64>,
+    stream_prefetch_chunks: Option<usize>,
+    max_concurrent_streams: Option<usize>,
+    list_cache_ttl_seconds: u64,
+    list_cache_max_prefixes: usize,
+    index_mode: bool,
+    index_refresh_seconds: u64,
+    allowed_origins: AllowedOrigins,
+    api_key: Option<String>,
+    /// Per-client-IP limit on `/api` requests; `None` leaves them unlimited.
+    rate_limit: Option<RateLimit>,
+    /// Take the client address from `X-Forwarded-For` (rate limiting only).
+    trust_proxy: bool,
+    /// Answer `/api` with 503 from startup.
+    maintenance_mode: bool,
+    /// Answer `/api` with 503 while this file exists.
+    maintenance_file: Option<PathBuf>,
+    /// Browser login; `None` unless `AUTH_USERNAME` and `AUTH_PASSWORD_HASH` are set.
+    session: Option<SessionConfig>,
+    shutdown_timeout_seconds: u64,
+    /// Directory for the daily-rotated JSON access log; stdout only when unset.
+    access_log_dir: Option<PathBuf>,
+    log_format: LogFormat,
+    workers: usize,
+    /// Zero turns keep-alive off.
+    keep_alive_seconds: u64,
+    /// Name of the threads this process starts itself; see `main`.
+    blocking_thread_name: String,
+    thumbnail_cache_dir: PathBuf,
+    thumbnails_enabled: bool,
+    ffmpeg_path: String,
+    max_concurrent_ffmpeg: usize,
+    ffprobe_path: String,
+    hls_segment_seconds: u64,
+    hls_assumed_bitrate_kbps: u64,
+    s3_max_retries: u32,
+    s3_connect_timeout_ms: u64,
+    s3_operation_timeout_ms: u64,
+    s3_slow_log_ms: u64,
+    server_timing: bool,
+    expose_s3_errors: bool,
+    events_poll_seconds: u64,
+    /// Check every bucket is reachable before serving, failing startup if not.
+    startup_check: bool,
+    tls: Option<TlsPaths>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Default)]
 #[allow(non_snake_case)]
 struct ListQuery {
     page: Option<usize>,
     pageSize: Option<usize>,
     prefix: Option<String>,
+    sort: Option<String>,
+    order: Option<String>,
+    recursive: Option<bool>,
+    search: Option<String>,
+    bucket: Option<String>,
+    modifiedAfter: Option<String>,
+    modifiedBefore: Option<String>,
+    minSize: Option<u64>,
+    maxSize: Option<u64>,
+    nativeOrder: Option<bool>,
+    continuationToken: Option<String>,
+    /// Opaque `nextCursor` from a previous page, or empty to start one.
+    /// Implies `nativeOrder`; a non-empty cursor replaces the other fields.
+    cursor: Option<String>,
+    foldersOnly: Option<bool>,
+    ext: Option<String>,
+    hideEmptyFolders: Option<bool>,
+    rawPrefix: Option<bool>,
+    withTags: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+struct StreamQuery {
+    expiry: Option<u64>,
+    /// Serves an older version of the object in a versioned bucket.
+    versionId: Option<String>,
+    /// `json` returns the presigned URL instead of redirecting to it.
+    format: Option<String>,
+}
+
+impl StreamQuery {
+    fn version_id(&self) -> Option<&str> {
+        self.versionId.as_deref().filter(|id| !id.is_empty())
+    }
+
+    fn wants_json(&self) -> Result<bool, ApiError> {
+        match self.format.as_deref() {
+            None | Some("redirect") => Ok(false),
+            Some("json") => Ok(true),
+            Some(_) => Err(ApiError::bad_request("format must be redirect or json")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PresignedUrlResponse {
+    url: String,
+    #[serde(rename = "expiresAt")]
+    expires_at: String,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum SortField {
+    Name,
+    Size,
+    Date,
+}
+
+impl SortField {
+    fn from_query(value: Option<&str>) -> Self {
+        match value.map(str::to_lowercase).as_deref() {
+            Some("size") => SortField::Size,
+            Some("date") => SortField::Date,
+            _ => SortField::Name,
+        }
+    }
 }
 
 #[derive(Clone, Serialize)]
 struct VideoItem {
+    /// ETag-derived identifier that stays the same while the object does.
+    id: String,
     key: String,
+    /// Human-friendly name for display only; streaming always uses `key`.
+    title: String,
     size: i64,
     #[serde(rename = "lastModified")]
     last_modified: Option<String>,
     #[serde(rename = "streamUrl")]
     stream_url: String,
+    /// Poster frame link, set only with `THUMBNAILS_ENABLED` and only for the
+    /// default bucket, which is all the thumbnail route serves.
+    #[serde(rename = "thumbnailUrl", skip_serializing_if = "Option::is_none")]
+    thumbnail_url: Option<String>,
+    /// S3 storage class, e.g. `STANDARD` or `GLACIER`, when S3 reports one.
+    #[serde(rename = "storageClass", skip_serializing_if = "Option::is_none")]
+    storage_class: Option<String>,
+    /// Sibling `.vtt`/`.srt` files; only filled in for video listings.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    subtitles: Vec<SubtitleTrack>,
+    /// S3 object tags, only fetched for listings with `withTags=true`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<HashMap<String, String>>,
+    #[serde(skip)]
+    modified_at: Option<DateTime>,
 }
 
 #[derive(Serialize)]
@@ -64,14 +427,212 @@ struct Pagination {
     has_next_page: bool,
     #[serde(rename = "hasPrevPage")]
     has_prev_page: bool,
+    #[serde(rename = "totalBytes")]
+    total_bytes: i64,
+    #[serde(rename = "pageBytes")]
+    page_bytes: i64,
+}
+
+/// A sub-folder of the listed prefix with the number of videos directly inside it.
+#[derive(Serialize)]
+struct FolderItem {
+    prefix: String,
+    /// The last segment of `prefix`, without the delimiter, for display.
+    name: String,
+    /// `null` when the folder wasn't counted (`foldersOnly` and `nativeOrder`
+    /// without `hideEmptyFolders`) or its own listing failed; a failure is also
+    /// reported in `warnings`.
+    #[serde(rename = "videoCount")]
+    video_count: Option<usize>,
+    /// The count covers only the first page of the folder's keys, so the
+    /// folder holds at least `videoCount` videos.
+    #[serde(
+        rename = "videoCountTruncated",
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    video_count_truncated: bool,
+    #[serde(skip)]
+    count_failed: bool,
+    /// Whether the folder has sub-folders of its own (that aren't hidden).
+    #[serde(skip)]
+    has_subfolders: bool,
+}
+
+impl FolderItem {
+    /// Known to hold nothing worth browsing: no videos in a complete count
+    /// and no sub-folders that might hold some.
+    fn is_empty(&self) -> bool {
+        self.video_count == Some(0) && !self.video_count_truncated && !self.has_subfolders
+    }
+}
+
+#[derive(Serialize)]
+struct Breadcrumb {
+    name: String,
+    prefix: String,
 }
 
 #[derive(Serialize)]
 struct ListResponse {
     prefix: String,
-    folders: Vec<String>,
+    breadcrumbs: Vec<Breadcrumb>,
+    folders: Vec<FolderItem>,
     videos: Vec<VideoItem>,
-    pagination: Pagination,
+    /// Absent for `nativeOrder` listings, which never count the whole prefix.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pagination: Option<Pagination>,
+    /// Token for the next `nativeOrder` page, absent on the last one.
+    #[serde(rename = "nextContinuationToken", skip_serializing_if = "Option::is_none")]
+    next_continuation_token: Option<String>,
+    /// Pass back as `cursor` for the next page; only set in cursor mode.
+    #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+    /// The page size actually used, after defaulting and clamping.
+    #[serde(rename = "pageSize")]
+    page_size: usize,
+    /// Present when `MAX_LIST_OBJECTS` stopped the listing early, so the
+    /// videos and totals only cover part of the prefix.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    /// Sub-listings that failed while the listing itself succeeded.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct ObjectListResponse {
+    prefix: String,
+    breadcrumbs: Vec<Breadcrumb>,
+    folders: Vec<FolderItem>,
+    objects: Vec<VideoItem>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pagination: Option<Pagination>,
+    #[serde(rename = "nextContinuationToken", skip_serializing_if = "Option::is_none")]
+    next_continuation_token: Option<String>,
+    #[serde(rename = "nextCursor", skip_serializing_if = "Option::is_none")]
+    next_cursor: Option<String>,
+    #[serde(rename = "pageSize")]
+    page_size: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct CountResponse {
+    #[serde(rename = "totalVideos")]
+    total_videos: usize,
+    #[serde(rename = "totalBytes")]
+    total_bytes: i64,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    truncated: bool,
+}
+
+#[derive(Serialize)]
+struct ObjectMetadata {
+    key: String,
+    #[serde(rename = "contentType")]
+    content_type: Option<String>,
+    #[serde(rename = "contentLength")]
+    content_length: Option<i64>,
+    #[serde(rename = "lastModified")]
+    last_modified: Option<String>,
+    #[serde(rename = "eTag")]
+    e_tag: Option<String>,
+    metadata: HashMap<String, String>,
+    /// S3 object tags; empty when the object has none.
+    tags: HashMap<String, String>,
+    #[serde(rename = "storageClass")]
+    storage_class: Option<String>,
+    #[serde(rename = "restoreStatus")]
+    restore_status: RestoreStatus,
+    #[serde(flatten)]
+    media: MediaInfo,
+}
+
+/// Progress of a restore from an archive tier, from `x-amz-restore`.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum RestoreStatus {
+    None,
+    Ongoing,
+    Restored,
+}
+
+impl RestoreStatus {
+    /// Reads a header like `ongoing-request="false", expiry-date="..."`.
+    fn from_header(restore: Option<&str>) -> Self {
+        match restore {
+            None => Self::None,
+            Some(value) if value.contains("ongoing-request=\"true\"") => Self::Ongoing,
+            Some(_) => Self::Restored,
+        }
+    }
+}
+
+/// Whether the object sits in Glacier Flexible Retrieval, Deep Archive or an
+/// Intelligent-Tiering archive tier. Glacier Instant Retrieval reads like any
+/// other class.
+fn in_archive_tier(head: &HeadObjectOutput) -> bool {
+    matches!(
+        head.storage_class(),
+        Some(StorageClass::Glacier | StorageClass::DeepArchive)
+    ) || head.archive_status().is_some()
+}
+
+/// Whether reads fail until the object is restored.
+fn is_archived(head: &HeadObjectOutput) -> bool {
+    in_archive_tier(head) && RestoreStatus::from_header(head.restore()) != RestoreStatus::Restored
+}
+
+/// One entry from `list_object_versions`: a stored version or a delete marker.
+#[derive(Serialize)]
+struct ObjectVersionItem {
+    #[serde(rename = "versionId")]
+    version_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    size: Option<i64>,
+    #[serde(rename = "lastModified")]
+    last_modified: Option<String>,
+    #[serde(rename = "isLatest")]
+    is_latest: bool,
+    #[serde(rename = "deleteMarker")]
+    delete_marker: bool,
+    #[serde(skip)]
+    modified_at: Option<DateTime>,
+}
+
+#[derive(Serialize)]
+struct ObjectVersionsResponse {
+    key: String,
+    versions: Vec<ObjectVersionItem>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum BatchMetadataItem {
+    Found(Box<ObjectMetadata>),
+    NotFound {
+        key: String,
+        #[serde(rename = "notFound")]
+        not_found: bool,
+    },
+}
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+}
+
+#[derive(Serialize)]
+struct ReadinessResponse {
+    status: &'static str,
+    bucket: String,
+    #[serde(rename = "latencyMs")]
+    latency_ms: u128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
 }
 
 fn parse_bool_env(value: Option<String>) -> bool {
@@ -84,134 +645,1663 @@ fn parse_bool_env(value: Option<String>) -> bool {
     )
 }
 
+const DEFAULT_VIDEO_EXTENSIONS: [&str; 5] = [".mp4", ".mov", ".avi", ".mkv", ".webm"];
+
+/// Parses a comma-separated extension list such as `mp4, .MOV` into
+/// lowercase, dot-prefixed entries, falling back to the defaults when empty.
+fn parse_extensions_env(value: Option<String>) -> Vec<String> {
+    let extensions: Vec<String> = value
+        .unwrap_or_default()
+        .split(',')
+        .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+        .filter(|ext| !ext.is_empty())
+        .map(|ext| format!(".{ext}"))
+        .collect();
+
+    if extensions.is_empty() {
+        DEFAULT_VIDEO_EXTENSIONS.iter().map(|ext| ext.to_string()).collect()
+    } else {
+        extensions
+    }
+}
+
+fn parse_log_format_env(value: Option<String>) -> Result<LogFormat> {
+    match value.unwrap_or_default().to_lowercase().as_str() {
+        "" | "pretty" => Ok(LogFormat::Pretty),
+        "json" => Ok(LogFormat::Json),
+        other => anyhow::bail!("Invalid LOG_FORMAT {other:?}, expected json or pretty"),
+    }
+}
+
+fn parse_stream_mode_env(value: Option<String>) -> Result<StreamMode> {
+    match value.unwrap_or_default().to_lowercase().as_str() {
+        "" | "redirect" => Ok(StreamMode::Redirect),
+        "proxy" => Ok(StreamMode::Proxy),
+        other => anyhow::bail!("Invalid STREAM_MODE {other:?}, expected proxy or redirect"),
+    }
+}
+
+/// Normalises `KEY_PREFIX` to `a/b/` form (or empty when unset).
+fn parse_key_prefix_env(value: Option<String>) -> Result<String> {
+    let trimmed = value.unwrap_or_default().trim().trim_matches('/').to_string();
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    if trimmed.split('/').any(|segment| segment.is_empty() || segment == "..") {
+        anyhow::bail!("KEY_PREFIX must not contain empty or '..' segments");
+    }
+    Ok(format!("{trimmed}/"))
+}
+
+/// Normalises `BASE_PATH` to `/a/b` form (or empty when unset) so it can be
+/// prepended to both route scopes and generated links.
+fn parse_base_path_env(value: Option<String>) -> Result<String> {
+    let trimmed = value.unwrap_or_default().trim().trim_matches('/').to_string();
+    if trimmed.is_empty() {
+        return Ok(String::new());
+    }
+    if trimmed
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        anyhow::bail!("BASE_PATH must not contain empty, '.' or '..' segments");
+    }
+    Ok(format!("/{trimmed}"))
+}
+
+/// Where the liveness route lives when `HEALTH_PATH` is unset.
+const DEFAULT_HEALTH_PATH: &str = "/api/health";
+
+/// Normalises `HEALTH_PATH` to `/a/b` form, defaulting to `/api/health`.
+/// Like every route it is mounted below `BASE_PATH`.
+fn parse_health_path_env(value: Option<String>) -> Result<String> {
+    let trimmed = value.unwrap_or_default().trim().trim_matches('/').to_string();
+    if trimmed.is_empty() {
+        return Ok(DEFAULT_HEALTH_PATH.to_string());
+    }
+    if trimmed
+        .split('/')
+        .any(|segment| segment.is_empty() || segment == "." || segment == "..")
+    {
+        anyhow::bail!("HEALTH_PATH must not contain empty, '.' or '..' segments");
+    }
+    Ok(format!("/{trimmed}"))
+}
+
+/// Parses `BIND_ADDRESS` as a bare IP (`[::1]` brackets allowed), defaulting
+/// to every IPv4 interface.
+fn parse_bind_address_env(value: Option<String>) -> Result<IpAddr> {
+    let value = value.unwrap_or_default();
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Ok(IpAddr::from([0, 0, 0, 0]));
+    }
+    trimmed
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .with_context(|| {
+            format!("Invalid BIND_ADDRESS {trimmed:?}, expected an IP address such as 127.0.0.1")
+        })
+}
+
+/// Reads a `Cache-Control` value: unset means `default`, empty turns the
+/// header off, and anything else must be a valid header value.
+fn parse_cache_control_env(
+    name: &str,
+    value: Option<String>,
+    default: &str,
+) -> Result<Option<String>> {
+    let value = value.unwrap_or_else(|| default.to_string());
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    if header::HeaderValue::from_str(value).is_err() {
+        anyhow::bail!("{name} is not a valid header value");
+    }
+    Ok(Some(value.to_string()))
+}
+
+fn parse_regex_env(name: &str, value: Option<String>) -> Result<Option<Regex>> {
+    value
+        .filter(|v| !v.is_empty())
+        .map(|pattern| Regex::new(&pattern).with_context(|| format!("Invalid {name} {pattern:?}")))
+        .transpose()
+}
+
+/// Parses `trailers/:604800,private/:300` into `(prefix, seconds)` pairs
+/// sorted longest prefix first. The split is at the last `:`, so prefixes may
+/// contain colons.
+fn parse_presign_expiry_overrides_env(value: Option<String>) -> Result<Vec<(String, u64)>> {
+    let mut overrides = value
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (prefix, seconds) = entry.rsplit_once(':').with_context(|| {
+                format!("PRESIGN_EXPIRY_OVERRIDES entry {entry:?} needs prefix:seconds")
+            })?;
+            let seconds = seconds
+                .trim()
+                .parse::<u64>()
+                .ok()
+                .filter(|seconds| (1..=MAX_PRESIGN_EXPIRY_SECONDS).contains(seconds))
+                .with_context(|| {
+                    format!(
+                        "PRESIGN_EXPIRY_OVERRIDES entry {entry:?} needs seconds between 1 and \
+                         {MAX_PRESIGN_EXPIRY_SECONDS}"
+                    )
+                })?;
+            Ok((prefix.trim().to_string(), seconds))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    overrides.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    Ok(overrides)
+}
+
+fn parse_allowed_origins_env(value: Option<String>) -> AllowedOrigins {
+    let value = value.unwrap_or_default();
+    if value.trim() == "*" {
+        return AllowedOrigins::Any;
+    }
+    let origins: Vec<String> = value
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect();
+    if origins.is_empty() {
+        AllowedOrigins::SameOrigin
+    } else {
+        AllowedOrigins::List(origins)
+    }
+}
+
+fn build_cors(allowed_origins: &AllowedOrigins) -> Cors {
+    let cors = Cors::default()
+        .allowed_methods([
+            Method::GET,
+            Method::HEAD,
+            Method::POST,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allowed_headers([
+            header::ACCEPT,
+            header::CONTENT_TYPE,
+            header::RANGE,
+            header::IF_NONE_MATCH,
+            header::IF_MODIFIED_SINCE,
+            header::HeaderName::from_static(auth::API_KEY_HEADER),
+        ])
+        .expose_headers([
+            header::CONTENT_RANGE,
+            header::ACCEPT_RANGES,
+            header::CONTENT_LENGTH,
+            header::RETRY_AFTER,
+            header::ETAG,
+            header::LAST_MODIFIED,
+        ])
+        .max_age(3600);
+
+    // With `*` actix-cors echoes whatever Origin arrives, so allowing
+    // credentials too would let any site ride a logged-in session.
+    match allowed_origins {
+        AllowedOrigins::SameOrigin => cors.supports_credentials(),
+        AllowedOrigins::Any => cors.allow_any_origin(),
+        AllowedOrigins::List(origins) => origins
+            .iter()
+            .fold(cors.supports_credentials(), |cors, origin| cors.allowed_origin(origin)),
+    }
+}
+
+/// Serves the frontend build. With `spa_fallback`, paths that match no file
+/// get `index.html` with a 200; otherwise they go to `not_found`. This only
+/// runs for requests that reach the static files, so unknown `/api` paths
+/// still get the API's 404.
+fn static_files(static_dir: &str, spa_fallback: bool) -> Files {
+    let files = Files::new("/", static_dir).index_file("index.html");
+    if !spa_fallback {
+        return files.default_handler(web::to(not_found));
+    }
+    let index_path = PathBuf::from(static_dir).join("index.html");
+    files.default_handler(fn_service(move |req: ServiceRequest| {
+        let index_path = index_path.clone();
+        async move {
+            let (req, _) = req.into_parts();
+            let response = NamedFile::open_async(&index_path).await?.into_response(&req);
+            Ok::<_, actix_web::Error>(ServiceResponse::new(req, response))
+        }
+    }))
+}
+
+/// Answers requests that no route or static file matched. API paths keep the
+/// JSON error body; with `NOT_FOUND_PAGE`, other GETs get `404.html`, still
+/// with a 404 status so crawlers and link checkers see the dead link.
+async fn not_found(req: HttpRequest, state: Data<AppState>) -> HttpResponse {
+    let api_prefix = format!("{}/api", state.base_path);
+    let is_api = req
+        .path()
+        .strip_prefix(&api_prefix)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+    if is_api {
+        return ApiError::not_found(format!("No route for {}", req.path())).error_response();
+    }
+
+    if matches!(*req.method(), Method::GET | Method::HEAD)
+        && let Some(page) = &state.not_found_page
+    {
+        match NamedFile::open_async(page).await {
+            Ok(file) => {
+                // Without validators a conditional request can't turn the
+                // 404 into a 304.
+                let mut response = file
+                    .use_etag(false)
+                    .use_last_modified(false)
+                    .into_response(&req);
+                *response.status_mut() = StatusCode::NOT_FOUND;
+                return response;
+            }
+            Err(err) => tracing::warn!("Failed to open {}: {err}", page.display()),
+        }
+    }
+    HttpResponse::NotFound().finish()
+}
+
+/// actix's default access log format with the request id appended.
+const ACCESS_LOG_FORMAT: &str =
+    r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T request_id=%{x-request-id}o"#;
+
+/// `Retry-After` sent when `MAX_CONCURRENT_STREAMS` is exhausted.
+const STREAM_RETRY_AFTER_SECONDS: u64 = 5;
+
+/// How many folder listings `list_videos` runs at once when counting videos.
+const FOLDER_COUNT_CONCURRENCY: usize = 8;
+
+/// Keys read to count one folder without the index: a single S3 page, so a
+/// folder with millions of objects costs one request, not a full listing.
+const FOLDER_COUNT_MAX_KEYS: i32 = 1000;
+
+/// Largest key list accepted by `POST /api/videos/meta/batch`.
+const MAX_METADATA_BATCH: usize = 100;
+
+/// How many `HeadObject` calls (and probes) a metadata batch runs at once.
+const METADATA_BATCH_CONCURRENCY: usize = 8;
+
+/// How many `GetObjectTagging` calls a `withTags` listing runs at once.
+const TAG_FETCH_CONCURRENCY: usize = 8;
+
+/// Connect and operation timeout for the readiness probe, overriding the
+/// client-wide S3 timeouts so a dead endpoint fails fast instead of holding
+/// the probe open until the orchestrator gives up.
+const READINESS_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// S3 refuses presigned URLs that are valid for longer than seven days.
+const MAX_PRESIGN_EXPIRY_SECONDS: u64 = 7 * 24 * 60 * 60;
+
+/// Where configuration values come from: environment variables first, then
+/// an optional TOML file whose keys use the same names as the env vars.
+struct ConfigSource {
+    env: HashMap<String, String>,
+    file: HashMap<String, String>,
+}
+
+impl ConfigSource {
+    /// Reads `CONFIG_FILE` (or `config.toml` when unset). A missing default
+    /// file is fine; a missing explicitly configured file is an error.
+    /// Variables that aren't valid UTF-8 are skipped, as `env::var` would.
+    fn load() -> Result<Self> {
+        let env: HashMap<String, String> = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        let (path, explicit) = match env.get("CONFIG_FILE") {
+            Some(path) if !path.is_empty() => (path.clone(), true),
+            _ => ("config.toml".to_string(), false),
+        };
+
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if !explicit && err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Self {
+                    env,
+                    file: HashMap::new(),
+                });
+            }
+            Err(err) => return Err(err).context(format!("Failed to read config file {path}")),
+        };
+
+        let file = Self::parse_file(&contents)
+            .with_context(|| format!("Failed to parse config file {path}"))?;
+        Ok(Self { env, file })
+    }
+
+    /// Flattens a TOML table into env-style values: keys upper-cased, arrays
+    /// joined with commas, everything else in its TOML spelling.
+    fn parse_file(contents: &str) -> Result<HashMap<String, String>> {
+        let table: toml::Table = toml::from_str(contents)?;
+        let file = table
+            .into_iter()
+            .map(|(key, value)| {
+                let value = match value {
+                    toml::Value::String(value) => value,
+                    toml::Value::Array(items) => items
+                        .into_iter()
+                        .map(|item| match item {
+                            toml::Value::String(item) => item,
+                            other => other.to_string(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    other => other.to_string(),
+                };
+                (key.to_uppercase(), value)
+            })
+            .collect();
+        Ok(file)
+    }
+
+    fn var(&self, name: &str) -> Option<String> {
+        self.env
+            .get(name)
+            .or_else(|| self.file.get(name))
+            .cloned()
+    }
+}
+
+/// The parsed value, or `fallback` after recording why it didn't parse. The
+/// fallback only lets loading go on to find the next problem: any problem
+/// fails `load_config`.
+fn check<T>(problems: &mut Vec<String>, result: Result<T>, fallback: T) -> T {
+    result.unwrap_or_else(|err| {
+        problems.push(format!("{err:#}"));
+        fallback
+    })
+}
+
+/// Fails with every collected problem in one error, if there are any.
+fn report_problems(problems: &[String]) -> Result<()> {
+    if !problems.is_empty() {
+        anyhow::bail!("Invalid configuration:\n  - {}", problems.join("\n  - "));
+    }
+    Ok(())
+}
+
+/// Reads the configuration, collecting every setting that fails to parse and
+/// reporting them all in one error. `AppConfig::validate` checks the rest.
 fn load_config() -> Result<AppConfig> {
-    let port = env::var("PORT")
-        .ok()
-        .and_then(|v| v.parse::<u16>().ok())
-        .unwrap_or(3000);
-
-    let static_dir = env::var("STATIC_DIR").unwrap_or_else(|_| "static".to_string());
-
-    let aws_region = env::var("AWS_REGION").context("Missing AWS_REGION")?;
-    let aws_access_key_id = env::var("AWS_ACCESS_KEY_ID").context("Missing AWS_ACCESS_KEY_ID")?;
-    let aws_secret_access_key =
-        env::var("AWS_SECRET_ACCESS_KEY").context("Missing AWS_SECRET_ACCESS_KEY")?;
-    let aws_s3_endpoint_url = env::var("AWS_S3_ENDPOINT_URL").ok();
-    let aws_s3_bucket_name = env::var("AWS_S3_BUCKET_NAME").context("Missing AWS_S3_BUCKET_NAME")?;
-    let aws_s3_force_path_style = parse_bool_env(env::var("AWS_S3_FORCE_PATH_STYLE").ok());
-
-    Ok(AppConfig {
-        port,
+    load_config_from(&ConfigSource::load()?)
+}
+
+fn load_config_from(source: &ConfigSource) -> Result<AppConfig> {
+    let mut problems = Vec::new();
+
+    let port = match source.var("PORT").filter(|v| !v.is_empty()) {
+        Some(v) => check(
+            &mut problems,
+            v.parse::<u16>()
+                .with_context(|| format!("Invalid PORT {v:?}, expected a number up to 65535")),
+            3000,
+        ),
+        None => 3000,
+    };
+    let bind_address = check(
+        &mut problems,
+        parse_bind_address_env(source.var("BIND_ADDRESS")),
+        IpAddr::from([0, 0, 0, 0]),
+    );
+    let bind_addr = SocketAddr::new(bind_address, port);
+
+    let static_dir = source.var("STATIC_DIR").unwrap_or_else(|| "static".to_string());
+    let spa_fallback = parse_bool_env(source.var("SPA_FALLBACK"));
+    let not_found_page = parse_bool_env(source.var("NOT_FOUND_PAGE"));
+    let base_path = check(
+        &mut problems,
+        parse_base_path_env(source.var("BASE_PATH")),
+        String::new(),
+    );
+    let health_path = check(
+        &mut problems,
+        parse_health_path_env(source.var("HEALTH_PATH")),
+        DEFAULT_HEALTH_PATH.to_string(),
+    );
+    let health_body = source.var("HEALTH_BODY").filter(|v| !v.is_empty());
+
+    let aws_access_key_id = source.var("AWS_ACCESS_KEY_ID").filter(|v| !v.is_empty());
+    let aws_secret_access_key = source.var("AWS_SECRET_ACCESS_KEY").filter(|v| !v.is_empty());
+    let aws_session_token = source.var("AWS_SESSION_TOKEN").filter(|v| !v.is_empty());
+    match (&aws_access_key_id, &aws_secret_access_key) {
+        (Some(_), None) => problems
+            .push("AWS_ACCESS_KEY_ID is set but AWS_SECRET_ACCESS_KEY is missing".to_string()),
+        (None, Some(_)) => problems
+            .push("AWS_SECRET_ACCESS_KEY is set but AWS_ACCESS_KEY_ID is missing".to_string()),
+        _ => {}
+    }
+    let aws_s3_endpoint_url = source.var("AWS_S3_ENDPOINT_URL").filter(|v| !v.is_empty());
+    let aws_s3_endpoint_url_fallback = source
+        .var("AWS_S3_ENDPOINT_URL_FALLBACK")
+        .filter(|v| !v.is_empty());
+    // S3-compatible providers behind a custom endpoint rarely care about the
+    // region, so only plain AWS insists on one.
+    let aws_region = match source.var("AWS_REGION").filter(|v| !v.is_empty()) {
+        Some(region) => region,
+        None if aws_s3_endpoint_url.is_some() => "us-east-1".to_string(),
+        None => {
+            problems.push("Missing AWS_REGION".to_string());
+            String::new()
+        }
+    };
+    let aws_s3_region = source.var("AWS_S3_REGION").filter(|v| !v.is_empty());
+    let mut aws_s3_bucket_names: Vec<String> = source
+        .var("AWS_S3_BUCKET_NAMES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|bucket| bucket.trim().to_string())
+        .filter(|bucket| !bucket.is_empty())
+        .collect();
+    let aws_s3_bucket_name = source
+        .var("AWS_S3_BUCKET_NAME")
+        .filter(|bucket| !bucket.is_empty())
+        .or_else(|| aws_s3_bucket_names.first().cloned())
+        .unwrap_or_else(|| {
+            problems.push("Missing AWS_S3_BUCKET_NAME".to_string());
+            String::new()
+        });
+    if !aws_s3_bucket_names.contains(&aws_s3_bucket_name) {
+        aws_s3_bucket_names.insert(0, aws_s3_bucket_name.clone());
+    }
+    let aws_s3_force_path_style = parse_bool_env(source.var("AWS_S3_FORCE_PATH_STYLE"));
+    let requester_pays = parse_bool_env(source.var("REQUESTER_PAYS"));
+    let key_prefix = check(
+        &mut problems,
+        parse_key_prefix_env(source.var("KEY_PREFIX")),
+        String::new(),
+    );
+    let delimiter = source
+        .var("DELIMITER")
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "/".to_string());
+    let folders_enabled = source
+        .var("FOLDERS_ENABLED")
+        .is_none_or(|v| parse_bool_env(Some(v)));
+    let presign_expiry_seconds = source.var("PRESIGN_EXPIRY_SECONDS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(3600);
+    if presign_expiry_seconds == 0 || presign_expiry_seconds > MAX_PRESIGN_EXPIRY_SECONDS {
+        problems.push(format!(
+            "PRESIGN_EXPIRY_SECONDS must be between 1 and {MAX_PRESIGN_EXPIRY_SECONDS}"
+        ));
+    }
+    let presign_expiry_overrides = check(
+        &mut problems,
+        parse_presign_expiry_overrides_env(source.var("PRESIGN_EXPIRY_OVERRIDES")),
+        Vec::new(),
+    );
+    let verify_before_presign = parse_bool_env(source.var("VERIFY_BEFORE_PRESIGN"));
+    let upload_url_expiry_seconds = source
+        .var("UPLOAD_URL_EXPIRY_SECONDS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(900);
+    if upload_url_expiry_seconds == 0 || upload_url_expiry_seconds > MAX_PRESIGN_EXPIRY_SECONDS {
+        problems.push(format!(
+            "UPLOAD_URL_EXPIRY_SECONDS must be between 1 and {MAX_PRESIGN_EXPIRY_SECONDS}"
+        ));
+    }
+    let list_cache_control = check(
+        &mut problems,
+        parse_cache_control_env(
+            "LIST_CACHE_CONTROL",
+            source.var("LIST_CACHE_CONTROL"),
+            "max-age=30",
+        ),
+        None,
+    );
+    let metadata_cache_control = check(
+        &mut problems,
+        parse_cache_control_env(
+            "METADATA_CACHE_CONTROL",
+            source.var("METADATA_CACHE_CONTROL"),
+            "max-age=60",
+        ),
+        None,
+    );
+    let default_page_size = source
+        .var("DEFAULT_PAGE_SIZE")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(18);
+    let max_page_size = source
+        .var("MAX_PAGE_SIZE")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(100);
+    if default_page_size > max_page_size {
+        problems.push(format!(
+            "DEFAULT_PAGE_SIZE must not exceed MAX_PAGE_SIZE ({max_page_size})"
+        ));
+    }
+    let max_list_objects = source
+        .var("MAX_LIST_OBJECTS")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0);
+    let video_extensions = parse_extensions_env(source.var("VIDEO_EXTENSIONS"));
+    let key_filter = KeyFilter {
+        allow: check(
+            &mut problems,
+            parse_regex_env("KEY_ALLOW_REGEX", source.var("KEY_ALLOW_REGEX")),
+            None,
+        ),
+        deny: check(
+            &mut problems,
+            parse_regex_env("KEY_DENY_REGEX", source.var("KEY_DENY_REGEX")),
+            None,
+        ),
+    };
+    let title_strip = check(
+        &mut problems,
+        parse_regex_env("TITLE_STRIP_REGEX", source.var("TITLE_STRIP_REGEX")),
+        None,
+    );
+    let hide_dotfiles = parse_bool_env(source.var("HIDE_DOTFILES"));
+    let stream_mode = check(
+        &mut problems,
+        parse_stream_mode_env(source.var("STREAM_MODE")),
+        StreamMode::Redirect,
+    );
+    let override_content_type = parse_bool_env(source.var("OVERRIDE_CONTENT_TYPE"));
+    let stream_max_bytes_per_sec = source
+        .var("STREAM_MAX_BYTES_PER_SEC")
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0);
+    let stream_prefetch_chunks = source
+        .var("STREAM_PREFETCH_CHUNKS")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0);
+    let max_concurrent_streams = source
+        .var("MAX_CONCURRENT_STREAMS")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0);
+    let list_cache_ttl_seconds = source.var("LIST_CACHE_TTL_SECONDS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+    let list_cache_max_prefixes = source.var("LIST_CACHE_MAX_PREFIXES")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(256);
+    let index_mode = parse_bool_env(source.var("INDEX_MODE"));
+    let index_refresh_seconds = source
+        .var("INDEX_REFRESH_SECONDS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(3600);
+    let allowed_origins = parse_allowed_origins_env(source.var("ALLOWED_ORIGINS"));
+    let api_key = source.var("API_KEY").filter(|key| !key.is_empty());
+    let rate_limit = source
+        .var("RATE_LIMIT_RPS")
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|v| v.is_finite() && *v > 0.0)
+        .map(|per_second| RateLimit {
+            per_second,
+            // The default burst is one second's worth, and never below one.
+            burst: source
+                .var("RATE_LIMIT_BURST")
+                .and_then(|v| v.parse::<u32>().ok())
+                .filter(|v| *v > 0)
+                .map_or(per_second.ceil(), f64::from),
+        });
+    let trust_proxy = parse_bool_env(source.var("TRUST_PROXY"));
+    let maintenance_mode = parse_bool_env(source.var("MAINTENANCE_MODE"));
+    let maintenance_file = source
+        .var("MAINTENANCE_FILE")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from);
+    let session_ttl_seconds = source
+        .var("SESSION_TTL_SECONDS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(12 * 60 * 60);
+    let session_cookie_secure = source
+        .var("SESSION_COOKIE_SECURE")
+        .is_none_or(|v| parse_bool_env(Some(v)));
+    let session = check(
+        &mut problems,
+        SessionConfig::from_vars(
+            source.var("AUTH_USERNAME"),
+            source.var("AUTH_PASSWORD_HASH"),
+            source.var("SESSION_SECRET"),
+            session_ttl_seconds,
+            session_cookie_secure,
+            format!("{base_path}/api"),
+        ),
+        None,
+    );
+    let shutdown_timeout_seconds = source.var("SHUTDOWN_TIMEOUT_SECONDS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(30);
+    let access_log_dir = source
+        .var("ACCESS_LOG_DIR")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from);
+    let log_format = check(
+        &mut problems,
+        parse_log_format_env(source.var("LOG_FORMAT")),
+        LogFormat::Pretty,
+    );
+    let workers = source
+        .var("WORKERS")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or_else(|| {
+            std::thread::available_parallelism().map_or(1, std::num::NonZeroUsize::get)
+        });
+    let keep_alive_seconds = source
+        .var("KEEP_ALIVE_SECONDS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(5);
+    let thumbnail_cache_dir = source
+        .var("THUMBNAIL_CACHE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::temp_dir().join("s3-streamer-thumbnails"));
+    let thumbnails_enabled = parse_bool_env(source.var("THUMBNAILS_ENABLED"));
+    let ffmpeg_path = source.var("FFMPEG_PATH").unwrap_or_else(|| "ffmpeg".to_string());
+    let max_concurrent_ffmpeg = source
+        .var("MAX_CONCURRENT_FFMPEG")
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(2);
+    let ffprobe_path = source.var("FFPROBE_PATH").unwrap_or_else(|| "ffprobe".to_string());
+    let blocking_thread_name = source
+        .var("BLOCKING_THREAD_NAME")
+        .unwrap_or_else(|| "s3-streamer-blocking".to_string());
+    let hls_segment_seconds = source
+        .var("HLS_SEGMENT_SECONDS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(6);
+    let hls_assumed_bitrate_kbps = source
+        .var("HLS_ASSUMED_BITRATE_KBPS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(5000);
+    let s3_max_retries = source
+        .var("S3_MAX_RETRIES")
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(2);
+    let s3_connect_timeout_ms = source
+        .var("S3_CONNECT_TIMEOUT_MS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(5_000);
+    let s3_operation_timeout_ms = source
+        .var("S3_OPERATION_TIMEOUT_MS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(30_000);
+    let s3_slow_log_ms = source
+        .var("S3_SLOW_LOG_MS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(1_000);
+    let server_timing = parse_bool_env(source.var("SERVER_TIMING"));
+    let expose_s3_errors = parse_bool_env(source.var("EXPOSE_S3_ERRORS"));
+    let events_poll_seconds = source
+        .var("EVENTS_POLL_SECONDS")
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(30);
+    let startup_check = parse_bool_env(source.var("STARTUP_CHECK"));
+    let tls = check(
+        &mut problems,
+        TlsPaths::from_vars(source.var("TLS_CERT_FILE"), source.var("TLS_KEY_FILE")),
+        None,
+    );
+
+    let config = AppConfig {
+        bind_addr,
         static_dir,
+        spa_fallback,
+        not_found_page,
+        base_path,
+        health_path,
+        health_body,
         aws_region,
+        aws_s3_region,
         aws_access_key_id,
         aws_secret_access_key,
+        aws_session_token,
         aws_s3_endpoint_url,
+        aws_s3_endpoint_url_fallback,
         aws_s3_bucket_name,
+        aws_s3_bucket_names,
         aws_s3_force_path_style,
+        requester_pays,
+        key_prefix,
+        delimiter,
+        folders_enabled,
+        presign_expiry_seconds,
+        presign_expiry_overrides,
+        verify_before_presign,
+        upload_url_expiry_seconds,
+        list_cache_control,
+        metadata_cache_control,
+        default_page_size,
+        max_page_size,
+        max_list_objects,
+        video_extensions,
+        key_filter,
+        title_strip,
+        hide_dotfiles,
+        stream_mode,
+        override_content_type,
+        stream_max_bytes_per_sec,
+        stream_prefetch_chunks,
+        max_concurrent_streams,
+        list_cache_ttl_seconds,
+        list_cache_max_prefixes,
+        index_mode,
+        index_refresh_seconds,
+        allowed_origins,
+        api_key,
+        rate_limit,
+        trust_proxy,
+        maintenance_mode,
+        maintenance_file,
+        session,
+        shutdown_timeout_seconds,
+        access_log_dir,
+        log_format,
+        workers,
+        keep_alive_seconds,
+        blocking_thread_name,
+        thumbnail_cache_dir,
+        thumbnails_enabled,
+        ffmpeg_path,
+        max_concurrent_ffmpeg,
+        ffprobe_path,
+        hls_segment_seconds,
+        hls_assumed_bitrate_kbps,
+        s3_max_retries,
+        s3_connect_timeout_ms,
+        s3_operation_timeout_ms,
+        s3_slow_log_ms,
+        server_timing,
+        expose_s3_errors,
+        events_poll_seconds,
+        startup_check,
+        tls,
+    };
+    report_problems(&problems)?;
+    Ok(config)
+}
+
+/// Whether `url` is an absolute `http` or `https` URL with a host.
+fn is_valid_endpoint_url(url: &str) -> bool {
+    url.parse::<Uri>().is_ok_and(|uri| {
+        matches!(uri.scheme_str(), Some("http" | "https")) && uri.host().is_some()
     })
 }
 
-async fn build_s3_client(config: &AppConfig) -> Result<Client> {
-    let region_provider = Region::new(config.aws_region.clone());
-    let credentials = Credentials::new(
-        &config.aws_access_key_id,
-        &config.aws_secret_access_key,
-        None,
+impl AppConfig {
+    /// Invariants that parsing each setting alone doesn't check, all reported
+    /// in one error.
+    fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if self.bind_addr.port() == 0 {
+            problems.push("PORT must not be 0".to_string());
+        }
+        if self.not_found_page && !PathBuf::from(&self.static_dir).join("404.html").is_file() {
+            problems.push(format!(
+                "NOT_FOUND_PAGE is set but {}/404.html is missing",
+                self.static_dir
+            ));
+        }
+        for (name, url) in [
+            ("AWS_S3_ENDPOINT_URL", &self.aws_s3_endpoint_url),
+            ("AWS_S3_ENDPOINT_URL_FALLBACK", &self.aws_s3_endpoint_url_fallback),
+        ] {
+            if let Some(url) = url
+                && !is_valid_endpoint_url(url)
+            {
+                problems.push(format!("{name} {url:?} is not an http(s) URL with a host"));
+            }
+        }
+        report_problems(&problems)
+    }
+}
+
+/// The configured access keys, with `AWS_SESSION_TOKEN` for temporary
+/// credentials; `None` when no keys are set.
+fn static_credentials(config: &AppConfig) -> Option<Credentials> {
+    let access_key_id = config.aws_access_key_id.as_ref()?;
+    let secret_access_key = config.aws_secret_access_key.as_ref()?;
+    Some(Credentials::new(
+        access_key_id,
+        secret_access_key,
+        config.aws_session_token.clone(),
         None,
         "env",
-    );
+    ))
+}
+
+async fn build_s3_client(config: &AppConfig, endpoint_url: Option<&str>) -> Result<Client> {
+    let region_provider = Region::new(config.aws_region.clone());
+
+    let timeout_config = TimeoutConfig::builder()
+        .connect_timeout(Duration::from_millis(config.s3_connect_timeout_ms))
+        .operation_timeout(Duration::from_millis(config.s3_operation_timeout_ms))
+        .build();
 
     let mut loader = aws_config::defaults(aws_config::BehaviorVersion::latest())
         .region(region_provider)
-        .credentials_provider(credentials);
+        .timeout_config(timeout_config);
+
+    // Explicit keys win; otherwise the default chain (env, profile, IRSA,
+    // instance metadata) resolves credentials.
+    if let Some(credentials) = static_credentials(config) {
+        loader = loader.credentials_provider(credentials);
+    }
 
-    if let Some(endpoint_url) = &config.aws_s3_endpoint_url {
+    if let Some(endpoint_url) = endpoint_url {
         loader = loader.endpoint_url(endpoint_url);
     }
 
     let shared_config = loader.load().await;
-    let mut s3_config_builder = aws_sdk_s3::config::Builder::from(&shared_config);
+    // Retries are handled by `RetryPolicy` around each call instead.
+    let mut s3_config_builder = aws_sdk_s3::config::Builder::from(&shared_config)
+        .retry_config(RetryConfig::disabled());
     if config.aws_s3_force_path_style {
         s3_config_builder = s3_config_builder.force_path_style(true);
     }
+    if let Some(s3_region) = &config.aws_s3_region {
+        s3_config_builder = s3_config_builder.region(Region::new(s3_region.clone()));
+    }
     let s3_config = s3_config_builder.build();
 
     Ok(Client::from_conf(s3_config))
 }
 
-fn common_prefix_to_string(prefix: &CommonPrefix) -> Option<String> {
-    prefix.prefix().map(|p| p.to_string())
+/// Sorts videos by the requested field. Ties fall back to the key so the
+/// order is stable across requests, and videos without a last-modified date
+/// always sort after dated ones regardless of direction.
+fn sort_videos(videos: &mut [VideoItem], field: SortField, descending: bool) {
+    let directed = |ordering: Ordering| {
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    };
+
+    videos.sort_by(|a, b| match field {
+        SortField::Name => directed(a.key.cmp(&b.key)),
+        SortField::Size => directed(a.size.cmp(&b.size)).then_with(|| a.key.cmp(&b.key)),
+        SortField::Date => match (&a.modified_at, &b.modified_at) {
+            (Some(a_date), Some(b_date)) => {
+                directed(a_date.cmp(b_date)).then_with(|| a.key.cmp(&b.key))
+            }
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => a.key.cmp(&b.key),
+        },
+    });
 }
 
-#[get("/videos")]
-async fn list_videos(state: Data<AppState>, query: Query<ListQuery>) -> actix_web::Result<impl Responder> {
-    let page = query.page.unwrap_or(1);
-    let mut page_size = query.pageSize.unwrap_or(18);
-    if page_size == 0 {
-        page_size = 18;
+/// Builds the stream link for a key; objects outside the default bucket get
+/// the bucket as an extra path segment.
+fn stream_url(base_path: &str, bucket: Option<&str>, key: &str) -> String {
+    match bucket {
+        Some(bucket) => format!(
+            "{base_path}/api/buckets/{}/videos/stream/{}",
+            urlencoding::encode(bucket),
+            urlencoding::encode(key)
+        ),
+        None => format!("{base_path}/api/videos/stream/{}", urlencoding::encode(key)),
     }
-    let prefix = query.prefix.clone().unwrap_or_default();
-
-    let response = state
-        .s3
-        .list_objects_v2()
-        .bucket(&state.bucket)
-        .prefix(&prefix)
-        .delimiter("/")
-        .max_keys(1000)
-        .send()
-        .await
-        .map_err(|err| {
-            actix_web::error::ErrorInternalServerError(format!(
-                "Failed to list videos: {err}"
-            ))
-        })?;
+}
 
-    let contents = response.contents();
-    let common_prefixes = response.common_prefixes();
+fn thumbnail_url(base_path: &str, key: &str) -> String {
+    format!("{base_path}/api/videos/thumbnail/{}", urlencoding::encode(key))
+}
 
-    let video_extensions = [".mp4", ".mov", ".avi", ".mkv", ".webm"];
+/// Returns the final segment of a key, e.g. `clip.mp4` for `a/b/clip.mp4`
+/// (or `a:b:clip.mp4` with a `:` delimiter).
+fn basename<'a>(key: &'a str, delimiter: &str) -> &'a str {
+    key.rsplit(delimiter).next().unwrap_or(key)
+}
 
-    let mut videos: Vec<VideoItem> = contents
-        .iter()
-        .filter_map(|item: &aws_sdk_s3::types::Object| {
-            let key = item.key()?.to_string();
-            let lower = key.to_lowercase();
-            if !video_extensions.iter().any(|ext| lower.ends_with(ext)) {
-                return None;
-            }
-            let size = item.size().unwrap_or(0);
-            let last_modified = item.last_modified().map(|dt| dt.to_string());
-            let stream_url = format!(
-                "/api/videos/stream/{}",
-                urlencoding::encode(&key)
-            );
-            Some(VideoItem {
-                key,
-                size,
-                last_modified,
-                stream_url,
+/// Parses an optional RFC 3339 query parameter, rejecting malformed values.
+fn parse_timestamp_param(name: &str, value: Option<&str>) -> Result<Option<DateTime>, ApiError> {
+    value
+        .filter(|value| !value.is_empty())
+        .map(|value| {
+            DateTime::from_str(value, DateTimeFormat::DateTime).map_err(|_| {
+                ApiError::bad_request(format!("{name} must be an RFC 3339 timestamp"))
             })
         })
-        .collect();
+        .transpose()
+}
 
-    videos.sort_by(|a, b| a.key.cmp(&b.key));
+/// Splits `a/b/c/` into crumbs for `a/`, `a/b/` and `a/b/c/`; the root is
+/// empty. Other delimiters split the same way.
+fn breadcrumbs(prefix: &str, delimiter: &str) -> Vec<Breadcrumb> {
+    let mut cumulative = String::new();
+    prefix
+        .split(delimiter)
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            cumulative.push_str(segment);
+            cumulative.push_str(delimiter);
+            Breadcrumb {
+                name: segment.to_string(),
+                prefix: cumulative.clone(),
+            }
+        })
+        .collect()
+}
 
-    let folders: Vec<String> = common_prefixes
+/// Sums object sizes, saturating rather than wrapping on absurdly large buckets.
+fn sum_sizes(videos: &[VideoItem]) -> i64 {
+    videos
         .iter()
-        .filter_map(common_prefix_to_string)
-        .collect();
+        .fold(0i64, |total, video| total.saturating_add(video.size.max(0)))
+}
+
+/// The display name of a folder prefix: `b` for `a/b/`.
+fn folder_name<'a>(prefix: &'a str, delimiter: &str) -> &'a str {
+    basename(prefix.strip_suffix(delimiter).unwrap_or(prefix), delimiter)
+}
+
+fn common_prefix_to_string(prefix: &CommonPrefix) -> Option<String> {
+    prefix.prefix().map(|p| p.to_string())
+}
+
+struct Listing {
+    objects: Vec<Object>,
+    common_prefixes: Vec<CommonPrefix>,
+    /// Set when `MAX_LIST_OBJECTS` cut the listing short.
+    truncated: bool,
+}
+
+/// Lists every object under `prefix`, following continuation tokens until S3
+/// reports the listing is complete or `max_objects` have been collected.
+/// Passing no delimiter lists every key at any depth and yields no common
+/// prefixes.
+async fn list_all_objects(
+    s3: &Client,
+    retry: &RetryPolicy,
+    bucket: &str,
+    prefix: &str,
+    delimiter: Option<&str>,
+    max_objects: Option<usize>,
+    request_payer: Option<&RequestPayer>,
+) -> Result<Listing, SdkError<ListObjectsV2Error>> {
+    let mut listing = Listing {
+        objects: Vec::new(),
+        common_prefixes: Vec::new(),
+        truncated: false,
+    };
+    let mut continuation_token: Option<String> = None;
+
+    loop {
+        // Never ask for more than the cap has room for, so a page can't
+        // overshoot it.
+        let max_keys = max_objects
+            .map_or(1000, |max| max.saturating_sub(listing.objects.len()).min(1000));
+        let response = retry
+            .run("list_objects_v2", || {
+                s3.list_objects_v2()
+                    .bucket(bucket)
+                    .prefix(prefix)
+                    .set_delimiter(delimiter.map(str::to_string))
+                    .max_keys(max_keys as i32)
+                    .set_continuation_token(continuation_token.clone())
+                    .set_request_payer(request_payer.cloned())
+                    .send()
+            })
+            .await?;
+
+        listing.objects.extend_from_slice(response.contents());
+        listing
+            .common_prefixes
+            .extend_from_slice(response.common_prefixes());
+
+        match response.next_continuation_token() {
+            Some(_) if max_objects.is_some_and(|max| listing.objects.len() >= max) => {
+                listing.truncated = response.is_truncated().unwrap_or(false);
+                break;
+            }
+            Some(token) if response.is_truncated().unwrap_or(false) => {
+                continuation_token = Some(token.to_string());
+            }
+            _ => break,
+        }
+    }
+
+    Ok(listing)
+}
+
+impl AppState {
+    fn new(
+        config: &AppConfig,
+        s3: Client,
+        s3_fallback: Option<Client>,
+        metrics: Data<Metrics>,
+    ) -> Self {
+        Self {
+            s3,
+            s3_fallback,
+            bucket: config.aws_s3_bucket_name.clone(),
+            allowed_buckets: config.aws_s3_bucket_names.clone(),
+            key_prefix: config.key_prefix.clone(),
+            delimiter: config.delimiter.clone(),
+            folders_enabled: config.folders_enabled,
+            request_payer: config.requester_pays.then_some(RequestPayer::Requester),
+            base_path: config.base_path.clone(),
+            not_found_page: config
+                .not_found_page
+                .then(|| PathBuf::from(&config.static_dir).join("404.html")),
+            health_body: config.health_body.clone(),
+            presign_expiry_seconds: config.presign_expiry_seconds,
+            presign_expiry_overrides: config.presign_expiry_overrides.clone(),
+            verify_before_presign: config.verify_before_presign,
+            upload_url_expiry_seconds: config.upload_url_expiry_seconds,
+            list_cache_control: config.list_cache_control.clone(),
+            metadata_cache_control: config.metadata_cache_control.clone(),
+            default_page_size: config.default_page_size,
+            max_page_size: config.max_page_size,
+            max_list_objects: config.max_list_objects,
+            video_extensions: config.video_extensions.clone(),
+            key_filter: config.key_filter.clone(),
+            title_strip: config.title_strip.clone(),
+            hide_dotfiles: config.hide_dotfiles,
+            stream_mode: config.stream_mode,
+            override_content_type: config.override_content_type,
+            stream_max_bytes_per_sec: config.stream_max_bytes_per_sec,
+            stream_prefetch_chunks: config.stream_prefetch_chunks,
+            stream_permits: config
+                .max_concurrent_streams
+                .map(|max| Arc::new(Semaphore::new(max))),
+            metrics,
+            list_cache: Arc::new(TtlCache::new(
+                Duration::from_secs(config.list_cache_ttl_seconds),
+                config.list_cache_max_prefixes,
+            )),
+            index: config.index_mode.then(|| Arc::new(ObjectIndex::new())),
+            thumbnail_dir: config.thumbnail_cache_dir.clone(),
+            thumbnails_enabled: config.thumbnails_enabled,
+            ffmpeg_path: config.ffmpeg_path.clone(),
+            ffmpeg_permits: Arc::new(Semaphore::new(config.max_concurrent_ffmpeg)),
+            ffprobe_path: config.ffprobe_path.clone(),
+            probe_cache: Arc::new(TtlCache::new(PROBE_CACHE_TTL, PROBE_CACHE_MAX_ENTRIES)),
+            hls_segment_seconds: config.hls_segment_seconds,
+            hls_assumed_bitrate_kbps: config.hls_assumed_bitrate_kbps,
+            retry: RetryPolicy::new(config.s3_max_retries),
+            s3_slow_log: (config.s3_slow_log_ms > 0)
+                .then(|| Duration::from_millis(config.s3_slow_log_ms)),
+            server_timing: config.server_timing,
+            expose_s3_errors: config.expose_s3_errors,
+        }
+    }
+
+    /// Picks the requested bucket, or the default, rejecting anything outside
+    /// the configured allow-list.
+    fn resolve_bucket<'a>(&'a self, requested: Option<&'a str>) -> Result<&'a str, ApiError> {
+        match requested.filter(|bucket| !bucket.is_empty()) {
+            None => Ok(&self.bucket),
+            Some(bucket) if self.allowed_buckets.iter().any(|allowed| allowed == bucket) => {
+                Ok(bucket)
+            }
+            Some(bucket) => Err(ApiError::forbidden(format!(
+                "Bucket {bucket:?} is not allowed"
+            ))),
+        }
+    }
+
+    /// The listing page size for a requested `pageSize`: missing or zero
+    /// means the configured default, and anything above the maximum is
+    /// clamped so one request can't force a huge slice.
+    fn page_size(&self, requested: Option<usize>) -> usize {
+        match requested {
+            None | Some(0) => self.default_page_size,
+            Some(size) => size.min(self.max_page_size),
+        }
+    }
+
+    fn is_video_key(&self, key: &str) -> bool {
+        let lower = key.to_lowercase();
+        self.video_extensions
+            .iter()
+            .any(|ext| lower.ends_with(ext.as_str()))
+    }
+
+    /// With `HIDE_DOTFILES`, whether `key` (or a folder prefix) is a dotfile
+    /// or lies below a dot folder such as `.trash/`.
+    fn is_hidden(&self, key: &str) -> bool {
+        self.hide_dotfiles
+            && key
+                .split(self.delimiter.as_str())
+                .any(|segment| segment.starts_with('.'))
+    }
+
+    /// The stream link lifetime for `key`: the longest matching
+    /// `PRESIGN_EXPIRY_OVERRIDES` prefix, else `PRESIGN_EXPIRY_SECONDS`.
+    fn presign_expiry_for(&self, key: &str) -> u64 {
+        self.presign_expiry_overrides
+            .iter()
+            .find(|(prefix, _)| key.starts_with(prefix.as_str()))
+            .map_or(self.presign_expiry_seconds, |(_, seconds)| *seconds)
+    }
+
+    /// Maps a key or prefix as clients see it to the real one under `KEY_PREFIX`.
+    fn s3_key(&self, key: &str) -> String {
+        format!("{}{key}", self.key_prefix)
+    }
+
+    /// Inverse of `s3_key`, used on everything S3 hands back.
+    fn client_key<'a>(&self, key: &'a str) -> &'a str {
+        key.strip_prefix(self.key_prefix.as_str()).unwrap_or(key)
+    }
+
+    /// Awaits an S3 call and warns when it exceeds `S3_SLOW_LOG_MS`. This is
+    /// plain logging, so it works whether or not metrics are scraped.
+    async fn time_s3<T>(
+        &self,
+        operation: &str,
+        bucket: &str,
+        target: &str,
+        call: impl Future<Output = T>,
+    ) -> T {
+        let started = Instant::now();
+        let result = call.await;
+        let elapsed = started.elapsed();
+        server_timing::record(operation, elapsed);
+        if self.s3_slow_log.is_some_and(|threshold| elapsed >= threshold) {
+            tracing::warn!(
+                operation,
+                bucket,
+                target,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "Slow S3 call"
+            );
+        }
+        result
+    }
+
+    /// Runs `call` against the primary S3 client and, if that endpoint can't
+    /// be reached and `AWS_S3_ENDPOINT_URL_FALLBACK` is set, once more against
+    /// the fallback. Every request starts on the primary again.
+    async fn with_failover<'a, T, E, Fut>(
+        &'a self,
+        operation: &str,
+        call: impl Fn(&'a Client) -> Fut,
+    ) -> Result<T, SdkError<E>>
+    where
+        Fut: Future<Output = Result<T, SdkError<E>>>,
+        E: std::error::Error + 'static,
+    {
+        match (call(&self.s3).await, &self.s3_fallback) {
+            (Err(err), Some(fallback)) if error::is_connection_failure(&err) => {
+                tracing::warn!(
+                    operation,
+                    "Primary S3 endpoint unreachable, failing over: {}",
+                    aws_sdk_s3::error::DisplayErrorContext(&err)
+                );
+                call(fallback).await
+            }
+            (result, _) => result,
+        }
+    }
+
+    /// Forgets cached listings that could include `key`: the folder holding
+    /// it and every recursive listing above it. The index is rebuilt too.
+    fn invalidate_listings(&self, bucket: &str, key: &str) {
+        self.list_cache.remove_where(|cache_key| {
+            let mut parts = cache_key.splitn(3, ':');
+            let (Some(cached_bucket), Some(_), Some(prefix)) =
+                (parts.next(), parts.next(), parts.next())
+            else {
+                return false;
+            };
+            cached_bucket == bucket && key.starts_with(prefix)
+        });
+        if let Some(index) = &self.index {
+            index.request_rebuild();
+        }
+    }
+
+    /// Whether a listing descends below the prefix: when asked to, and always
+    /// with folders disabled, where the delimiter is never sent.
+    fn recursive(&self, requested: bool) -> bool {
+        requested || !self.folders_enabled
+    }
+
+    /// The Content-Type to force on a response, if overriding is enabled.
+    fn content_type_override(&self, key: &str) -> Option<&'static str> {
+        self.override_content_type.then(|| content_type_for_key(key))
+    }
+}
+
+/// Returns the listing for `prefix`, served from the index or the TTL cache
+/// when possible.
+async fn cached_listing(
+    state: &AppState,
+    bucket: &str,
+    prefix: &str,
+    recursive: bool,
+) -> Result<Arc<Listing>, ApiError> {
+    if let Some(listing) = state
+        .index
+        .as_ref()
+        .and_then(|index| {
+            let delimiter = (!recursive).then_some(state.delimiter.as_str());
+            index.listing(bucket, &state.s3_key(prefix), delimiter)
+        })
+    {
+        return Ok(Arc::new(listing));
+    }
+
+    let delimiter = (!recursive).then_some(state.delimiter.as_str());
+    let mode = if recursive { "recursive" } else { "folder" };
+    let cache_key = format!("{bucket}:{mode}:{prefix}");
+
+    if let Some(listing) = state.list_cache.get(&cache_key) {
+        return Ok(listing);
+    }
+
+    let s3_prefix = state.s3_key(prefix);
+    let listing = state
+        .time_s3(
+            "list_objects_v2",
+            bucket,
+            &s3_prefix,
+            state.with_failover("list_objects_v2", |s3| {
+                list_all_objects(
+                    s3,
+                    &state.retry,
+                    bucket,
+                    &s3_prefix,
+                    delimiter,
+                    state.max_list_objects,
+                    state.request_payer.as_ref(),
+                )
+            }),
+        )
+        .await
+        .map_err(|err| {
+            state.metrics.record_s3_failure("list_objects_v2");
+            ApiError::from_s3("Failed to list videos", err)
+        })?;
+    let listing = Arc::new(listing);
+    state.list_cache.insert(cache_key, listing.clone());
+    Ok(listing)
+}
+
+/// Weak ETag over the serialized listing, so any change to the page (new
+/// objects, different sort, different filters) yields a new tag. The hash is
+/// `stable_hash`, so every replica behind a load balancer agrees on it.
+fn listing_etag(body: &[u8]) -> EntityTag {
+    EntityTag::new_weak(format!("{:016x}", stable_hash(body)))
+}
+
+/// Whether the client's `If-None-Match` already covers `etag`.
+fn etag_matches(req: &HttpRequest, etag: &EntityTag) -> bool {
+    match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        Err(_) => false,
+    }
+}
+
+/// The search, size and date filters shared by both listing modes.
+struct VideoFilters {
+    search: Option<String>,
+    /// One of the configured extensions, in the same `.mp4` form.
+    extension: Option<String>,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    modified_after: Option<DateTime>,
+    modified_before: Option<DateTime>,
+    /// `search` matches the name after the last delimiter.
+    delimiter: String,
+}
+
+impl VideoFilters {
+    fn from_query(
+        query: &ListQuery,
+        video_extensions: &[String],
+        delimiter: &str,
+    ) -> Result<Self, ApiError> {
+        let extension = match query.ext.as_deref().map(str::trim).filter(|ext| !ext.is_empty()) {
+            Some(ext) => {
+                let ext = format!(".{}", ext.trim_start_matches('.').to_lowercase());
+                if !video_extensions.contains(&ext) {
+                    return Err(ApiError::bad_request(format!(
+                        "ext must be one of {}",
+                        video_extensions.join(", ")
+                    )));
+                }
+                Some(ext)
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            extension,
+            search: query
+                .search
+                .as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_lowercase),
+            min_size: query.minSize,
+            max_size: query.maxSize,
+            modified_after: parse_timestamp_param("modifiedAfter", query.modifiedAfter.as_deref())?,
+            modified_before: parse_timestamp_param(
+                "modifiedBefore",
+                query.modifiedBefore.as_deref(),
+            )?,
+            delimiter: delimiter.to_string(),
+        })
+    }
+
+    fn matches(&self, video: &VideoItem) -> bool {
+        let name_matches = self
+            .search
+            .as_ref()
+            .is_none_or(|needle| {
+                basename(&video.key, &self.delimiter)
+                    .to_lowercase()
+                    .contains(needle)
+            });
+
+        let size = u64::try_from(video.size).unwrap_or(0);
+        // Any lower bound also hides empty placeholder objects.
+        let size_matches = self.min_size.is_none_or(|min| size > 0 && size >= min)
+            && self.max_size.is_none_or(|max| size <= max);
+
+        let date_matches = (self.modified_after.is_none() && self.modified_before.is_none())
+            || video.modified_at.as_ref().is_some_and(|modified| {
+                self.modified_after.as_ref().is_none_or(|after| modified > after)
+                    && self.modified_before.as_ref().is_none_or(|before| modified < before)
+            });
+
+        let extension_matches = self
+            .extension
+            .as_ref()
+            .is_none_or(|ext| video.key.to_lowercase().ends_with(ext.as_str()));
+
+        name_matches && extension_matches && size_matches && date_matches
+    }
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher`, whose algorithm may change between
+/// Rust releases, the same bytes hash the same in every build, so ids and
+/// ETags survive upgrades and agree across replicas.
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// A stable identifier for an object: its ETag without the quotes S3 wraps
+/// it in, or a hash of the key when there is no ETag. Re-uploads change it.
+fn object_id(key: &str, e_tag: Option<&str>) -> String {
+    match e_tag.map(|tag| tag.trim_matches('"')).filter(|tag| !tag.is_empty()) {
+        Some(tag) => tag.to_string(),
+        None => format!("key-{:016x}", stable_hash(key.as_bytes())),
+    }
+}
+
+/// Turns `2023/raw_dump_final_v2.mp4` into `raw dump final v2`: the last
+/// path segment without its extension, with `TITLE_STRIP_REGEX` matches
+/// removed and underscores and dashes read as spaces. Falls back to the bare
+/// file name if nothing is left.
+fn display_title(key: &str, delimiter: &str, strip: Option<&Regex>) -> String {
+    let name = key.rsplit(delimiter).next().unwrap_or(key);
+    let stem = match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    };
+    let stripped = match strip {
+        Some(regex) => regex.replace_all(stem, ""),
+        None => stem.into(),
+    };
+    let title = stripped
+        .split(|c: char| c == '_' || c == '-' || c.is_whitespace())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if title.is_empty() {
+        name.to_string()
+    } else {
+        title
+    }
+}
+
+/// Turns a listed object into a `VideoItem`, skipping non-video keys unless
+/// `videos_only` is off.
+fn listed_item(
+    state: &AppState,
+    link_bucket: Option<&str>,
+    item: &Object,
+    videos_only: bool,
+) -> Option<VideoItem> {
+    let key = state.client_key(item.key()?).to_string();
+    if videos_only && !state.is_video_key(&key) {
+        return None;
+    }
+    if state.is_hidden(&key) || !state.key_filter.allows(&key) {
+        return None;
+    }
+    let size = item.size().unwrap_or(0);
+    let modified_at = item.last_modified().cloned();
+    let last_modified = modified_at.as_ref().map(|dt| dt.to_string());
+    let stream_url = stream_url(&state.base_path, link_bucket, &key);
+    let thumbnail_url = (state.thumbnails_enabled && videos_only && link_bucket.is_none())
+        .then(|| thumbnail_url(&state.base_path, &key));
+    Some(VideoItem {
+        id: object_id(&key, item.e_tag()),
+        title: display_title(&key, &state.delimiter, state.title_strip.as_ref()),
+        key,
+        size,
+        last_modified,
+        stream_url,
+        thumbnail_url,
+        storage_class: item.storage_class().map(|class| class.as_str().to_string()),
+        subtitles: Vec::new(),
+        tags: None,
+        modified_at,
+    })
+}
+
+/// The keys a folder's count is taken from: the index's view when there is
+/// one, otherwise the first page of a shallow listing, kept in the listing
+/// cache so repeat visits to the parent don't list every child again.
+/// `truncated` says the folder has more keys than were read.
+async fn folder_sample(
+    state: &AppState,
+    bucket: &str,
+    folder_prefix: &str,
+) -> Result<Arc<Listing>, ApiError> {
+    let s3_prefix = state.s3_key(folder_prefix);
+    if let Some(listing) = state
+        .index
+        .as_ref()
+        .and_then(|index| index.listing(bucket, &s3_prefix, Some(&state.delimiter)))
+    {
+        return Ok(Arc::new(listing));
+    }
+
+    let cache_key = format!("{bucket}:sample:{folder_prefix}");
+    if let Some(sample) = state.list_cache.get(&cache_key) {
+        return Ok(sample);
+    }
+
+    let list_page = state.with_failover("list_objects_v2", |s3| {
+        state.retry.run("list_objects_v2", || {
+            s3.list_objects_v2()
+                .bucket(bucket)
+                .prefix(&s3_prefix)
+                .delimiter(&state.delimiter)
+                .max_keys(FOLDER_COUNT_MAX_KEYS)
+                .set_request_payer(state.request_payer.clone())
+                .send()
+        })
+    });
+    let response = state
+        .time_s3("list_objects_v2", bucket, &s3_prefix, list_page)
+        .await
+        .map_err(|err| {
+            state.metrics.record_s3_failure("list_objects_v2");
+            ApiError::from_s3("Failed to list folder", err)
+        })?;
+    let sample = Arc::new(Listing {
+        objects: response.contents().to_vec(),
+        common_prefixes: response.common_prefixes().to_vec(),
+        truncated: response.is_truncated().unwrap_or(false),
+    });
+    state.list_cache.insert(cache_key, sample.clone());
+    Ok(sample)
+}
+
+/// Counts the videos directly inside each folder, a few folders at a time.
+/// Each count reads at most one page of keys, so past that it is a lower
+/// bound and says so. A folder whose listing fails is still returned,
+/// without a count, so one bad prefix doesn't sink the whole page.
+async fn folder_items(
+    state: &AppState,
+    bucket: &str,
+    mut folder_prefixes: Vec<String>,
+) -> Vec<FolderItem> {
+    folder_prefixes.sort();
+    stream::iter(folder_prefixes)
+        .map(|folder_prefix| async move {
+            let (video_count, video_count_truncated, has_subfolders) =
+                match folder_sample(state, bucket, &folder_prefix).await {
+                    Ok(sample) => {
+                        let count = sample
+                            .objects
+                            .iter()
+                            .filter_map(|item| item.key())
+                            .map(|key| state.client_key(key))
+                            .filter(|key| {
+                                state.is_video_key(key)
+                                    && !state.is_hidden(key)
+                                    && state.key_filter.allows(key)
+                            })
+                            .count();
+                        let has_subfolders = sample
+                            .common_prefixes
+                            .iter()
+                            .filter_map(common_prefix_to_string)
+                            .any(|folder| !state.is_hidden(state.client_key(&folder)));
+                        (Some(count), sample.truncated, has_subfolders)
+                    }
+                    Err(err) => {
+                        tracing::warn!("Failed to count videos in {folder_prefix:?}: {err}");
+                        (None, false, false)
+                    }
+                };
+            FolderItem {
+                name: folder_name(&folder_prefix, &state.delimiter).to_string(),
+                prefix: folder_prefix,
+                count_failed: video_count.is_none(),
+                video_count,
+                video_count_truncated,
+                has_subfolders,
+            }
+        })
+        .buffered(FOLDER_COUNT_CONCURRENCY)
+        .collect()
+        .await
+}
+
+/// The folders without their counts, for listings that skip the per-folder
+/// S3 calls. `videoCount` is `null` and no warning is raised.
+fn uncounted_folders(state: &AppState, mut folder_prefixes: Vec<String>) -> Vec<FolderItem> {
+    folder_prefixes.sort();
+    folder_prefixes
+        .into_iter()
+        .map(|prefix| FolderItem {
+            name: folder_name(&prefix, &state.delimiter).to_string(),
+            prefix,
+            video_count: None,
+            video_count_truncated: false,
+            count_failed: false,
+            has_subfolders: false,
+        })
+        .collect()
+}
+
+/// Upper bound on S3 calls spent filling one `nativeOrder` page, so prefixes
+/// full of non-video keys return a short page instead of stalling.
+const NATIVE_PAGE_MAX_REQUESTS: usize = 10;
+
+/// One page of a listing, shared by `/videos` and `/objects`.
+struct ListingPage {
+    prefix: String,
+    items: Vec<VideoItem>,
+    folders: Vec<FolderItem>,
+    pagination: Option<Pagination>,
+    next_continuation_token: Option<String>,
+    next_cursor: Option<String>,
+    page_size: usize,
+    truncated: bool,
+    warnings: Vec<String>,
+}
+
+/// Lists the whole prefix, then sorts, filters and slices it by page number.
+async fn buffered_listing(
+    state: &AppState,
+    query: &ListQuery,
+    bucket: &str,
+    prefix: &str,
+    filters: &VideoFilters,
+    page_size: usize,
+    videos_only: bool,
+) -> Result<(Vec<VideoItem>, Vec<FolderItem>, Option<Pagination>, bool), ApiError> {
+    let page = query.page.unwrap_or(1);
+    let link_bucket = (bucket != state.bucket).then_some(bucket);
+    let recursive = state.recursive(query.recursive.unwrap_or(false));
+    let listing = cached_listing(state, bucket, prefix, recursive).await?;
+
+    let mut videos: Vec<VideoItem> = listing
+        .objects
+        .iter()
+        .filter_map(|item| listed_item(state, link_bucket, item, videos_only))
+        .filter(|video| filters.matches(video))
+        .collect();
+
+    let sort_field = SortField::from_query(query.sort.as_deref());
+    let descending = query
+        .order
+        .as_deref()
+        .is_some_and(|order| order.eq_ignore_ascii_case("desc"));
+    sort_videos(&mut videos, sort_field, descending);
+
+    let folder_prefixes = listing
+        .common_prefixes
+        .iter()
+        .filter_map(common_prefix_to_string)
+        .map(|folder| state.client_key(&folder).to_string())
+        .filter(|folder| !state.is_hidden(folder))
+        .collect();
+    let folders = folder_items(state, bucket, folder_prefixes).await;
 
     let total_videos = videos.len();
-    let total_pages = (total_videos + page_size - 1) / page_size;
+    let total_bytes = sum_sizes(&videos);
+    if videos_only {
+        state.metrics.set_last_list_video_count(total_videos);
+    }
+    let total_pages = total_videos.div_ceil(page_size);
     let start_index = page.saturating_sub(1) * page_size;
     let end_index = std::cmp::min(start_index + page_size, total_videos);
-    let paginated_videos = if start_index >= total_videos {
+    let mut paginated_videos = if start_index >= total_videos {
         vec![]
     } else {
         videos[start_index..end_index].to_vec()
     };
+    if videos_only {
+        subtitles::attach_subtitles(state, &mut paginated_videos, &listing.objects, link_bucket);
+    }
 
     let pagination = Pagination {
         page,
@@ -220,75 +2310,2253 @@ async fn list_videos(state: Data<AppState>, query: Query<ListQuery>) -> actix_we
         total_videos,
         has_next_page: page < total_pages,
         has_prev_page: page > 1,
+        total_bytes,
+        page_bytes: sum_sizes(&paginated_videos),
+    };
+
+    Ok((paginated_videos, folders, Some(pagination), listing.truncated))
+}
+
+/// Walks S3 in its own key order straight from `continuationToken`, asking
+/// only for as many keys as the page still has room for so no listed video
+/// is ever skipped. Sorting is ignored and totals are unknown in this mode.
+async fn native_order_listing(
+    state: &AppState,
+    query: &ListQuery,
+    bucket: &str,
+    prefix: &str,
+    filters: &VideoFilters,
+    page_size: usize,
+    videos_only: bool,
+) -> Result<(Vec<VideoItem>, Vec<FolderItem>, Option<String>), ApiError> {
+    let link_bucket = (bucket != state.bucket).then_some(bucket);
+    let recursive = state.recursive(query.recursive.unwrap_or(false));
+    let delimiter = (!recursive).then_some(state.delimiter.as_str());
+    let s3_prefix = state.s3_key(prefix);
+    let mut continuation_token = query.continuationToken.clone().filter(|t| !t.is_empty());
+    let mut videos = Vec::new();
+    let mut folder_prefixes = Vec::new();
+
+    for _ in 0..NATIVE_PAGE_MAX_REQUESTS {
+        let remaining = page_size - videos.len();
+        let list_page = state.with_failover("list_objects_v2", |s3| {
+            state.retry.run("list_objects_v2", || {
+                s3.list_objects_v2()
+                    .bucket(bucket)
+                    .prefix(&s3_prefix)
+                    .set_delimiter(delimiter.map(str::to_string))
+                    .max_keys(i32::try_from(remaining).unwrap_or(1000).min(1000))
+                    .set_continuation_token(continuation_token.clone())
+                    .set_request_payer(state.request_payer.clone())
+                    .send()
+            })
+        });
+        let response = state
+            .time_s3("list_objects_v2", bucket, &s3_prefix, list_page)
+            .await
+            .map_err(|err| {
+                state.metrics.record_s3_failure("list_objects_v2");
+                ApiError::from_s3("Failed to list videos", err)
+            })?;
+
+        let first_new = videos.len();
+        videos.extend(
+            response
+                .contents()
+                .iter()
+                .filter_map(|item| listed_item(state, link_bucket, item, videos_only))
+                .filter(|video| filters.matches(video)),
+        );
+        // Only sidecars within the same S3 page can be matched in this mode.
+        if videos_only {
+            subtitles::attach_subtitles(
+                state,
+                &mut videos[first_new..],
+                response.contents(),
+                link_bucket,
+            );
+        }
+        folder_prefixes.extend(
+            response
+                .common_prefixes()
+                .iter()
+                .filter_map(common_prefix_to_string)
+                .map(|folder| state.client_key(&folder).to_string())
+                .filter(|folder| !state.is_hidden(folder)),
+        );
+
+        continuation_token = response
+            .next_continuation_token()
+            .filter(|_| response.is_truncated().unwrap_or(false))
+            .map(str::to_string);
+        if continuation_token.is_none() || videos.len() >= page_size {
+            break;
+        }
+    }
+
+    // Counting would list every folder on every chunk, which is the cost
+    // this mode exists to avoid; only `hideEmptyFolders` pays for it.
+    let folders = if query.hideEmptyFolders.unwrap_or(false) {
+        folder_items(state, bucket, folder_prefixes).await
+    } else {
+        uncounted_folders(state, folder_prefixes)
+    };
+    Ok((videos, folders, continuation_token))
+}
+
+/// Lists just the sub-folders of `prefix`, skipping the per-object filtering
+/// and sorting. Folders are only counted when `with_counts` asks for it
+/// (`hideEmptyFolders` needs the counts). The pagination block is kept,
+/// zeroed, so the shape matches. With folders disabled there is nothing to
+/// list, so S3 isn't asked.
+async fn folders_only_listing(
+    state: &AppState,
+    bucket: &str,
+    prefix: &str,
+    page_size: usize,
+    with_counts: bool,
+) -> Result<(Vec<FolderItem>, Pagination, bool), ApiError> {
+    let (folders, truncated) = if state.folders_enabled {
+        let listing = cached_listing(state, bucket, prefix, false).await?;
+        let folder_prefixes = listing
+            .common_prefixes
+            .iter()
+            .filter_map(common_prefix_to_string)
+            .map(|folder| state.client_key(&folder).to_string())
+            .filter(|folder| !state.is_hidden(folder))
+            .collect();
+        let folders = if with_counts {
+            folder_items(state, bucket, folder_prefixes).await
+        } else {
+            uncounted_folders(state, folder_prefixes)
+        };
+        (folders, listing.truncated)
+    } else {
+        (Vec::new(), false)
+    };
+
+    let pagination = Pagination {
+        page: 1,
+        page_size,
+        total_pages: 0,
+        total_videos: 0,
+        has_next_page: false,
+        has_prev_page: false,
+        total_bytes: 0,
+        page_bytes: 0,
+    };
+    Ok((folders, pagination, truncated))
+}
+
+/// Treats `folder` as the folder `folder/` (or whatever `delimiter` is), so
+/// the delimiter splits the same way either form is sent. `raw` keeps the
+/// prefix as given, for matching on partial names; the root stays empty.
+fn normalize_prefix(mut prefix: String, raw: bool, delimiter: &str) -> String {
+    if !raw && !prefix.is_empty() && !prefix.ends_with(delimiter) {
+        prefix.push_str(delimiter);
+    }
+    prefix
+}
+
+/// Resolves the bucket and listing mode from the query and fetches one page.
+async fn listing_page(
+    state: &AppState,
+    query: &ListQuery,
+    videos_only: bool,
+) -> Result<ListingPage, ApiError> {
+    let cursor_mode = query.cursor.is_some();
+    let cursor_query;
+    let query = match query.cursor.as_deref().filter(|cursor| !cursor.is_empty()) {
+        Some(cursor) => {
+            cursor_query = ListCursor::decode(cursor)?.into_query();
+            &cursor_query
+        }
+        None => query,
     };
+    let page_size = state.page_size(query.pageSize);
+    let prefix = normalize_prefix(
+        query.prefix.clone().unwrap_or_default(),
+        query.rawPrefix.unwrap_or(false),
+        &state.delimiter,
+    );
+    validate_key(&prefix, &state.delimiter)?;
+    let filters = VideoFilters::from_query(query, &state.video_extensions, &state.delimiter)?;
+    let bucket = state.resolve_bucket(query.bucket.as_deref())?;
+
+    let (mut items, mut folders, pagination, next_continuation_token, truncated) =
+        if query.foldersOnly.unwrap_or(false) {
+            let hide_empty = query.hideEmptyFolders.unwrap_or(false);
+            let (folders, pagination, truncated) =
+                folders_only_listing(state, bucket, &prefix, page_size, hide_empty).await?;
+            (Vec::new(), folders, Some(pagination), None, truncated)
+        } else if query.nativeOrder.unwrap_or(false) || cursor_mode {
+            let (items, folders, token) = native_order_listing(
+                state, query, bucket, &prefix, &filters, page_size, videos_only,
+            )
+            .await?;
+            (items, folders, None, token, false)
+        } else {
+            let (items, folders, pagination, truncated) = buffered_listing(
+                state, query, bucket, &prefix, &filters, page_size, videos_only,
+            )
+            .await?;
+            (items, folders, pagination, None, truncated)
+        };
+    if query.withTags.unwrap_or(false) {
+        attach_tags(state, bucket, &mut items).await;
+    }
+    // `folder_items` already counted each folder's direct videos with bounded
+    // concurrency, so hiding empty ones costs no extra listings. Folders with
+    // sub-folders, partial counts or no count at all are kept.
+    if query.hideEmptyFolders.unwrap_or(false) {
+        folders.retain(|folder| !folder.is_empty());
+    }
+    let warnings = folders
+        .iter()
+        .filter(|folder| folder.count_failed)
+        .map(|folder| format!("Could not list folder {}", folder.prefix))
+        .collect();
+    let next_cursor = next_continuation_token
+        .clone()
+        .filter(|_| cursor_mode)
+        .map(|token| ListCursor::new(query, token).encode());
 
-    Ok(HttpResponse::Ok().json(ListResponse {
+    Ok(ListingPage {
         prefix,
+        items,
         folders,
-        videos: paginated_videos,
         pagination,
-    }))
+        next_continuation_token,
+        next_cursor,
+        page_size,
+        truncated,
+        warnings,
+    })
+}
+
+/// Serializes a listing and answers `If-None-Match` with 304 when unchanged.
+/// Both carry `LIST_CACHE_CONTROL` so revalidated copies stay fresh too.
+fn etag_json_response(
+    req: &HttpRequest,
+    state: &AppState,
+    value: &impl Serialize,
+) -> Result<HttpResponse, ApiError> {
+    let body = serde_json::to_vec(value)
+        .map_err(|err| ApiError::internal(format!("Failed to serialize listing: {err}")))?;
+
+    let etag = listing_etag(&body);
+    let not_modified = etag_matches(req, &etag);
+    let mut response = if not_modified {
+        HttpResponse::NotModified()
+    } else {
+        HttpResponse::Ok()
+    };
+    response.insert_header(header::ETag(etag));
+    if let Some(cache_control) = &state.list_cache_control {
+        response.insert_header((header::CACHE_CONTROL, cache_control.as_str()));
+    }
+    if not_modified {
+        return Ok(response.finish());
+    }
+    Ok(response.insert_header(header::ContentType::json()).body(body))
 }
 
-#[get("/videos/stream/{key:.*}")]
-async fn stream_video(state: Data<AppState>, path: Path<String>) -> actix_web::Result<HttpResponse> {
-    let raw_key = path.into_inner();
-    let decoded_key = urlencoding::decode(&raw_key)
-        .map_err(|_| actix_web::error::ErrorBadRequest("Invalid key encoding"))?;
+/// A 200 JSON response carrying `METADATA_CACHE_CONTROL`.
+fn metadata_json_response(state: &AppState, value: &impl Serialize) -> HttpResponse {
+    let mut response = HttpResponse::Ok();
+    if let Some(cache_control) = &state.metadata_cache_control {
+        response.insert_header((header::CACHE_CONTROL, cache_control.as_str()));
+    }
+    response.json(value)
+}
 
-    let presign_config = PresigningConfig::expires_in(Duration::from_secs(3600))
-        .map_err(|err| actix_web::error::ErrorInternalServerError(err))?;
+#[get("/videos")]
+async fn list_videos(
+    req: HttpRequest,
+    state: Data<AppState>,
+    query: Query<ListQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let page = listing_page(&state, &query, true).await?;
+    etag_json_response(
+        &req,
+        &state,
+        &ListResponse {
+            breadcrumbs: breadcrumbs(&page.prefix, &state.delimiter),
+            prefix: page.prefix,
+            folders: page.folders,
+            videos: page.items,
+            pagination: page.pagination,
+            next_continuation_token: page.next_continuation_token,
+            next_cursor: page.next_cursor,
+            page_size: page.page_size,
+            truncated: page.truncated,
+            warnings: page.warnings,
+        },
+    )
+}
 
-    let presigned = state
-        .s3
-        .get_object()
-        .bucket(&state.bucket)
-        .key(decoded_key.as_ref())
-        .presigned(presign_config)
+/// Same as `/videos` but without the extension filter, so sidecar files such
+/// as subtitles and posters show up too.
+#[get("/objects")]
+async fn list_objects(
+    req: HttpRequest,
+    state: Data<AppState>,
+    query: Query<ListQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let page = listing_page(&state, &query, false).await?;
+    etag_json_response(
+        &req,
+        &state,
+        &ObjectListResponse {
+            breadcrumbs: breadcrumbs(&page.prefix, &state.delimiter),
+            prefix: page.prefix,
+            folders: page.folders,
+            objects: page.items,
+            pagination: page.pagination,
+            next_continuation_token: page.next_continuation_token,
+            next_cursor: page.next_cursor,
+            page_size: page.page_size,
+            truncated: page.truncated,
+            warnings: page.warnings,
+        },
+    )
+}
+
+/// Just the totals `/videos` would report for the same query, for summary
+/// widgets that poll without needing the items themselves.
+#[get("/videos/count")]
+async fn count_videos(
+    req: HttpRequest,
+    state: Data<AppState>,
+    query: Query<ListQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let prefix = normalize_prefix(
+        query.prefix.clone().unwrap_or_default(),
+        query.rawPrefix.unwrap_or(false),
+        &state.delimiter,
+    );
+    validate_key(&prefix, &state.delimiter)?;
+    let filters = VideoFilters::from_query(&query, &state.video_extensions, &state.delimiter)?;
+    let bucket = state.resolve_bucket(query.bucket.as_deref())?;
+    let recursive = state.recursive(query.recursive.unwrap_or(false));
+    let listing = cached_listing(&state, bucket, &prefix, recursive).await?;
+
+    let link_bucket = (bucket != state.bucket).then_some(bucket);
+    let (total_videos, total_bytes) = listing
+        .objects
+        .iter()
+        .filter_map(|item| listed_item(&state, link_bucket, item, true))
+        .filter(|video| filters.matches(video))
+        .fold((0usize, 0i64), |(count, bytes), video| {
+            (count + 1, bytes.saturating_add(video.size.max(0)))
+        });
+
+    etag_json_response(
+        &req,
+        &state,
+        &CountResponse {
+            total_videos,
+            total_bytes,
+            truncated: listing.truncated,
+        },
+    )
+}
+
+/// Picks one matching video uniformly at random. Each listing page is
+/// sampled as it arrives (a reservoir of one), so the prefix is never
+/// buffered whole. Unlike `/videos` this searches sub-folders unless
+/// `recursive=false`.
+#[get("/videos/random")]
+async fn random_video(
+    state: Data<AppState>,
+    query: Query<ListQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let prefix = normalize_prefix(
+        query.prefix.clone().unwrap_or_default(),
+        query.rawPrefix.unwrap_or(false),
+        &state.delimiter,
+    );
+    validate_key(&prefix, &state.delimiter)?;
+    let filters = VideoFilters::from_query(&query, &state.video_extensions, &state.delimiter)?;
+    let bucket = state.resolve_bucket(query.bucket.as_deref())?;
+    let link_bucket = (bucket != state.bucket).then_some(bucket);
+    let recursive = state.recursive(query.recursive.unwrap_or(true));
+    let delimiter = (!recursive).then_some(state.delimiter.as_str());
+    let s3_prefix = state.s3_key(&prefix);
+    let mut continuation_token: Option<String> = None;
+    let mut matched = 0u64;
+    let mut chosen = None;
+
+    loop {
+        let list_page = state.retry.run("list_objects_v2", || {
+            state
+                .s3
+                .list_objects_v2()
+                .bucket(bucket)
+                .prefix(&s3_prefix)
+                .set_delimiter(delimiter.map(str::to_string))
+                .max_keys(1000)
+                .set_continuation_token(continuation_token.clone())
+                .set_request_payer(state.request_payer.clone())
+                .send()
+        });
+        let response = state
+            .time_s3("list_objects_v2", bucket, &s3_prefix, list_page)
+            .await
+            .map_err(|err| {
+                state.metrics.record_s3_failure("list_objects_v2");
+                ApiError::from_s3("Failed to list videos", err)
+            })?;
+
+        // The n-th match replaces the pick with probability 1/n, leaving
+        // every match equally likely once the listing ends.
+        for video in response
+            .contents()
+            .iter()
+            .filter_map(|item| listed_item(&state, link_bucket, item, true))
+            .filter(|video| filters.matches(video))
+        {
+            matched += 1;
+            if fastrand::u64(..matched) == 0 {
+                chosen = Some(video);
+            }
+        }
+
+        match response.next_continuation_token() {
+            Some(token) if response.is_truncated().unwrap_or(false) => {
+                continuation_token = Some(token.to_string());
+            }
+            _ => break,
+        }
+    }
+
+    let video = chosen.ok_or_else(|| ApiError::not_found("No videos match"))?;
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .json(video))
+}
+
+async fn head_object(state: &AppState, key: &str) -> Result<HeadObjectOutput, ApiError> {
+    head_object_in(state, &state.bucket, key, None).await
+}
+
+async fn head_object_in(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    version_id: Option<&str>,
+) -> Result<HeadObjectOutput, ApiError> {
+    let s3_key = state.s3_key(key);
+    let head = state.with_failover("head_object", |s3| {
+        state.retry.run("head_object", || {
+            s3.head_object()
+                .bucket(bucket)
+                .key(&s3_key)
+                .set_version_id(version_id.map(str::to_string))
+                .set_request_payer(state.request_payer.clone())
+                .send()
+        })
+    });
+    state
+        .time_s3("head_object", bucket, &s3_key, head)
         .await
         .map_err(|err| {
-            actix_web::error::ErrorInternalServerError(format!(
-                "Failed to presign URL: {err}"
-            ))
-        })?;
+            state.metrics.record_s3_failure("head_object");
+            ApiError::from_s3("Failed to read object metadata", err)
+        })
+}
 
-    Ok(HttpResponse::Found()
-        .append_header((header::LOCATION, presigned.uri().to_string()))
-        .finish())
+/// An object's tags as a map, empty for untagged objects. Tags are extras,
+/// so a failure (typically a missing `s3:GetObjectTagging` permission) is
+/// logged and read as no tags rather than failing the request.
+async fn object_tags(state: &AppState, bucket: &str, key: &str) -> HashMap<String, String> {
+    let s3_key = state.s3_key(key);
+    let tagging = state.with_failover("get_object_tagging", |s3| {
+        state.retry.run("get_object_tagging", || {
+            s3.get_object_tagging()
+                .bucket(bucket)
+                .key(&s3_key)
+                .set_request_payer(state.request_payer.clone())
+                .send()
+        })
+    });
+    match state
+        .time_s3("get_object_tagging", bucket, &s3_key, tagging)
+        .await
+    {
+        Ok(output) => output
+            .tag_set()
+            .iter()
+            .map(|tag| (tag.key().to_string(), tag.value().to_string()))
+            .collect(),
+        Err(err) => {
+            state.metrics.record_s3_failure("get_object_tagging");
+            tracing::warn!("Failed to read tags of {key:?}: {err}");
+            HashMap::new()
+        }
+    }
 }
 
-#[actix_web::main]
-async fn main() -> Result<()> {
-    let _ = dotenvy::dotenv();
+/// Fills in `tags` for one listing page, a few objects at a time.
+async fn attach_tags(state: &AppState, bucket: &str, items: &mut [VideoItem]) {
+    let tags: Vec<HashMap<String, String>> = stream::iter(items.iter())
+        .map(|item| object_tags(state, bucket, &item.key))
+        .buffered(TAG_FETCH_CONCURRENCY)
+        .collect()
+        .await;
+    for (item, tags) in items.iter_mut().zip(tags) {
+        item.tags = Some(tags);
+    }
+}
 
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env())
-        .init();
+/// Checks a key captured by a `{key:.*}` route. The `Path` extractor has
+/// already percent-decoded it exactly once, which is the inverse of the
+/// `urlencoding::encode` behind every link we hand out; decoding again would
+/// turn keys containing a literal `%20` or `%2F` into different keys. `+` is
+/// kept as is, since it only means a space in query strings.
+fn decode_key(key: &str, delimiter: &str) -> Result<String, ApiError> {
+    validate_key(key, delimiter)?;
+    Ok(key.to_string())
+}
 
-    let config = load_config()?;
+/// Rejects keys that look like path traversal, split at `/` and at the
+/// configured `delimiter`. S3 itself would treat them as literal names, but
+/// they never come from our own listings.
+fn validate_key(key: &str, delimiter: &str) -> Result<(), ApiError> {
+    if key.starts_with('/') || key.starts_with(delimiter) {
+        return Err(ApiError::bad_request(format!(
+            "Keys must not start with {delimiter:?}"
+        )));
+    }
+    if key
+        .split('/')
+        .chain(key.split(delimiter))
+        .any(|segment| segment == "..")
+    {
+        return Err(ApiError::bad_request("Keys must not contain '..' segments"));
+    }
+    if key.chars().any(char::is_control) {
+        return Err(ApiError::bad_request("Keys must not contain control characters"));
+    }
+    Ok(())
+}
 
-    let s3_client = build_s3_client(&config).await?;
-    let state = Data::new(AppState {
-        s3: s3_client,
-        bucket: config.aws_s3_bucket_name.clone(),
-    });
+/// Guesses the MIME type from the key's extension, for objects uploaded
+/// without a usable Content-Type.
+fn content_type_for_key(key: &str) -> &'static str {
+    mime_guess::from_path(key)
+        .first_raw()
+        .unwrap_or("application/octet-stream")
+}
 
-    let bind_addr = format!("0.0.0.0:{}", config.port);
+/// Adapts an S3 body into the chunk stream actix expects for streaming responses.
+fn byte_stream_body(body: ByteStream) -> impl Stream<Item = Result<Bytes, ByteStreamError>> {
+    stream::unfold(body, |mut body| async move {
+        body.next().await.map(|chunk| (chunk, body))
+    })
+}
 
-    HttpServer::new(move || {
-        App::new()
-            .app_data(state.clone())
-            .wrap(Logger::default())
-            .service(
-                web::scope("/api")
-                    .service(list_videos)
-                    .service(stream_video),
-            )
-            .service(Files::new("/", &config.static_dir).index_file("index.html"))
+/// Reads up to `chunks` S3 chunks ahead of the client on a spawned task, so
+/// a slow S3 read doesn't leave the socket idle. The bounded channel is the
+/// backpressure: the pump waits while it is full. A client that disconnects
+/// drops the receiver, which stops the pump even mid-read.
+fn prefetch_body(
+    mut body: ByteStream,
+    chunks: usize,
+) -> impl Stream<Item = Result<Bytes, ByteStreamError>> {
+    let (sender, receiver) = mpsc::channel(chunks);
+    let pump = async move {
+        loop {
+            let chunk = match future::select(pin!(body.next()), pin!(sender.closed())).await {
+                Either::Left((Some(chunk), _)) => chunk,
+                Either::Left((None, _)) | Either::Right(_) => break,
+            };
+            if sender.send(chunk).await.is_err() {
+                break;
+            }
+        }
+    };
+    actix_web::rt::spawn(pump.instrument(tracing::Span::current()));
+    stream::unfold(receiver, |mut receiver| async move {
+        receiver.recv().await.map(|chunk| (chunk, receiver))
     })
-    .bind(bind_addr)?
-    .run()
-    .await?;
+}
 
-    Ok(())
+/// Polls `inner` inside `span`. Bodies are pumped after the handler has
+/// returned, outside the request span, so without this anything logged while
+/// streaming (a panic included) would lose its `request_id`.
+fn in_span<S: Stream>(inner: S, span: tracing::Span) -> impl Stream<Item = S::Item> {
+    stream::unfold((Box::pin(inner), span), |(mut inner, span)| async move {
+        let item = inner.next().instrument(span.clone()).await?;
+        Some((item, (inner, span)))
+    })
+}
+
+async fn redirect_to_presigned(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    query: &StreamQuery,
+    content_disposition: Option<&str>,
+) -> Result<HttpResponse, ApiError> {
+    // A requested expiry may shorten the link but never outlive the configured one.
+    let configured_expiry = state.presign_expiry_for(key);
+    let expiry_seconds = match query.expiry {
+        Some(0) => return Err(ApiError::bad_request("Expiry must be positive")),
+        Some(expiry) if expiry > MAX_PRESIGN_EXPIRY_SECONDS => {
+            return Err(ApiError::bad_request(format!(
+                "Expiry must not exceed {MAX_PRESIGN_EXPIRY_SECONDS} seconds"
+            )));
+        }
+        Some(expiry) => expiry.min(configured_expiry),
+        None => configured_expiry,
+    };
+
+    let wants_json = query.wants_json()?;
+    let presign_config = PresigningConfig::expires_in(Duration::from_secs(expiry_seconds))
+        .map_err(|err| ApiError::internal(format!("Invalid presign expiry: {err}")))?;
+    let expires_at = SystemTime::now() + Duration::from_secs(expiry_seconds);
+
+    // Presigning never touches S3, so without this an archived object
+    // redirects to a URL that only fails once the browser follows it. A
+    // missing key is a 404 only with VERIFY_BEFORE_PRESIGN; otherwise a failed
+    // check still redirects.
+    match head_object_in(state, bucket, key, query.version_id()).await {
+        Ok(head) if is_archived(&head) => {
+            return Err(ApiError::archived(format!(
+                "{key} is archived and must be restored before it can be streamed"
+            )));
+        }
+        Err(err) if state.verify_before_presign => return Err(err),
+        Ok(_) | Err(_) => {}
+    }
+
+    let s3_key = state.s3_key(key);
+    let presign = state
+        .s3
+        .get_object()
+        .bucket(bucket)
+        .key(&s3_key)
+        .set_version_id(query.version_id().map(str::to_string))
+        .set_response_content_disposition(content_disposition.map(str::to_string))
+        .set_response_content_type(state.content_type_override(key).map(str::to_string))
+        .set_request_payer(state.request_payer.clone())
+        .presigned(presign_config);
+    let presigned = state
+        .time_s3("presign_get_object", bucket, &s3_key, presign)
+        .await
+        .map_err(|err| {
+            state.metrics.record_s3_failure("presign_get_object");
+            ApiError::from_s3("Failed to presign URL", err)
+        })?;
+
+    // The presigned URL expires, so neither the redirect nor the URL in it may
+    // be reused from a cache.
+    if wants_json {
+        let expires_at = DateTime::from(expires_at)
+            .fmt(DateTimeFormat::DateTime)
+            .map_err(|err| ApiError::internal(format!("Invalid expiry time: {err}")))?;
+        return Ok(HttpResponse::Ok()
+            .insert_header((header::CACHE_CONTROL, "no-store"))
+            .json(PresignedUrlResponse {
+                url: presigned.uri().to_string(),
+                expires_at,
+            }));
+    }
+    Ok(HttpResponse::Found()
+        .append_header((header::LOCATION, presigned.uri().to_string()))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .finish())
+}
+
+async fn proxy_object(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    version_id: Option<&str>,
+    req: &HttpRequest,
+    content_disposition: Option<&str>,
+) -> Result<HttpResponse, ApiError> {
+    // The permit moves into the body below, so it is released once the body
+    // finishes or is dropped because the client went away.
+    let permit = match &state.stream_permits {
+        Some(permits) => Some(permits.clone().try_acquire_owned().map_err(|_| {
+            tracing::warn!("Concurrent stream limit reached, rejecting {key}");
+            ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "too_many_streams",
+                "Too many concurrent streams, try again shortly",
+            )
+            .with_retry_after(STREAM_RETRY_AFTER_SECONDS)
+        })?),
+        None => None,
+    };
+
+    // S3 understands the same `bytes=` syntax as browsers, so the header is
+    // passed through untouched and S3 reports the satisfied range back.
+    // Malformed headers are ignored, as RFC 7233 asks, and get the full body.
+    let range = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_range(value))
+        .map(str::to_string);
+    // Conditional headers go to S3 too, so players re-requesting on seek get
+    // a bodiless 304 for an object they already have.
+    let if_none_match = req
+        .headers()
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let if_modified_since = req
+        .headers()
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::from_str(value, DateTimeFormat::HttpDate).ok());
+
+    let s3_key = state.s3_key(key);
+    let get = state.with_failover("get_object", |s3| {
+        state.retry.run("get_object", || {
+            s3.get_object()
+                .bucket(bucket)
+                .key(&s3_key)
+                .set_version_id(version_id.map(str::to_string))
+                .set_range(range.clone())
+                .set_if_none_match(if_none_match.clone())
+                .set_if_modified_since(if_modified_since)
+                .set_request_payer(state.request_payer.clone())
+                .send()
+        })
+    });
+    let object = match state.time_s3("get_object", bucket, &s3_key, get).await {
+        Ok(object) => object,
+        Err(err) if error::is_not_modified(&err) => {
+            let mut response = HttpResponse::NotModified();
+            // S3 repeats the validators on a 304; pass them on unchanged.
+            if let Some(raw) = err.raw_response() {
+                for name in [header::ETAG, header::LAST_MODIFIED] {
+                    if let Some(value) = raw.headers().get(name.as_str()) {
+                        response.insert_header((name, value));
+                    }
+                }
+            }
+            return Ok(response.finish());
+        }
+        Err(err) if range.is_some() && error::is_invalid_range(&err) => {
+            return range_not_satisfiable(state, bucket, key, version_id).await;
+        }
+        Err(err) => {
+            state.metrics.record_s3_failure("get_object");
+            return Err(ApiError::from_s3("Failed to fetch object", err));
+        }
+    };
+
+    let mut response = match object.content_range() {
+        Some(content_range) => {
+            let mut response = HttpResponse::PartialContent();
+            response.insert_header((header::CONTENT_RANGE, content_range));
+            response
+        }
+        None => HttpResponse::Ok(),
+    };
+    response.insert_header((header::ACCEPT_RANGES, "bytes"));
+    // Video bytes don't shrink, and compressing would invalidate both the
+    // Content-Length and any Content-Range S3 reported, so opt out of `Compress`.
+    response.insert_header(header::ContentEncoding::Identity);
+    if let Some(content_type) = state.content_type_override(key).or(object.content_type()) {
+        response.insert_header((header::CONTENT_TYPE, content_type));
+    }
+    if let Some(content_disposition) = content_disposition {
+        response.insert_header((header::CONTENT_DISPOSITION, content_disposition));
+    }
+    if let Some(e_tag) = object.e_tag() {
+        response.insert_header((header::ETAG, e_tag));
+    }
+    if let Some(last_modified) = object
+        .last_modified()
+        .and_then(|dt| dt.fmt(DateTimeFormat::HttpDate).ok())
+    {
+        response.insert_header((header::LAST_MODIFIED, last_modified));
+    }
+    if let Some(content_length) = object.content_length().and_then(|len| u64::try_from(len).ok()) {
+        response.no_chunking(content_length);
+    }
+
+    // Throttling only paces the body, so lengths and ranges are unaffected.
+    let body = match state.stream_prefetch_chunks {
+        Some(chunks) => prefetch_body(object.body, chunks).left_stream(),
+        None => byte_stream_body(object.body).right_stream(),
+    }
+    .map(move |chunk| {
+        let _permit = &permit;
+        chunk
+    });
+    let span = tracing::Span::current();
+    Ok(match state.stream_max_bytes_per_sec {
+        Some(bytes_per_sec) => {
+            response.streaming(in_span(throttle::throttle(body, bytes_per_sec), span))
+        }
+        None => response.streaming(in_span(body, span)),
+    })
+}
+
+/// Checks a `Range` header against the `bytes=` grammar: comma-separated
+/// `first-last`, `first-` or `-suffix` specs with `first <= last`.
+fn is_valid_range(value: &str) -> bool {
+    let Some(specs) = value.trim().strip_prefix("bytes=") else {
+        return false;
+    };
+    let is_number = |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+    specs.split(',').map(str::trim).all(|spec| match spec.split_once('-') {
+        Some(("", suffix)) => is_number(suffix),
+        Some((first, "")) => is_number(first),
+        Some((first, last)) => {
+            is_number(first)
+                && is_number(last)
+                && matches!(
+                    (first.parse::<u64>(), last.parse::<u64>()),
+                    (Ok(first), Ok(last)) if first <= last
+                )
+        }
+        None => false,
+    })
+}
+
+/// Answers a range that starts past the end of the object with 416 and the
+/// `Content-Range: bytes */<size>` header RFC 7233 requires.
+async fn range_not_satisfiable(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    version_id: Option<&str>,
+) -> Result<HttpResponse, ApiError> {
+    let head = head_object_in(state, bucket, key, version_id).await?;
+
+    let mut response = ApiError::new(
+        StatusCode::RANGE_NOT_SATISFIABLE,
+        "range_not_satisfiable",
+        "Requested range is beyond the end of the object",
+    )
+    .error_response();
+    if let Some(size) = head.content_length()
+        && let Ok(value) = header::HeaderValue::from_str(&format!("bytes */{size}"))
+    {
+        response.headers_mut().insert(header::CONTENT_RANGE, value);
+    }
+    Ok(response)
+}
+
+fn presigned_url_unavailable() -> ApiError {
+    ApiError::bad_request(
+        "format=json needs STREAM_MODE=redirect; proxy mode never exposes S3 URLs",
+    )
+}
+
+async fn stream_object(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    query: &StreamQuery,
+    req: &HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    if req.method() == Method::HEAD {
+        return head_response(state, bucket, key, query.version_id()).await;
+    }
+    match state.stream_mode {
+        StreamMode::Redirect => redirect_to_presigned(state, bucket, key, query, None).await,
+        StreamMode::Proxy if query.wants_json()? => Err(presigned_url_unavailable()),
+        StreamMode::Proxy => {
+            proxy_object(state, bucket, key, query.version_id(), req, None).await
+        }
+    }
+}
+
+/// Answers `HEAD` in either stream mode with the object's length, type and
+/// validators but no body, so players probing before playback get the real
+/// size instead of a redirect or an error.
+async fn head_response(
+    state: &AppState,
+    bucket: &str,
+    key: &str,
+    version_id: Option<&str>,
+) -> Result<HttpResponse, ApiError> {
+    let head = head_object_in(state, bucket, key, version_id).await?;
+    let content_type = state
+        .content_type_override(key)
+        .or(head.content_type())
+        .unwrap_or_else(|| content_type_for_key(key));
+
+    let mut response = HttpResponse::Ok();
+    response.insert_header((header::ACCEPT_RANGES, "bytes"));
+    response.insert_header(header::ContentEncoding::Identity);
+    response.insert_header((header::CONTENT_TYPE, content_type));
+    if let Some(e_tag) = head.e_tag() {
+        response.insert_header((header::ETAG, e_tag));
+    }
+    if let Some(last_modified) = head
+        .last_modified()
+        .and_then(|dt| dt.fmt(DateTimeFormat::HttpDate).ok())
+    {
+        response.insert_header((header::LAST_MODIFIED, last_modified));
+    }
+    // The sized empty body sets Content-Length; HEAD responses never send it.
+    let content_length = head.content_length().and_then(|len| u64::try_from(len).ok());
+    response.no_chunking(content_length.unwrap_or(0));
+    Ok(response.streaming(stream::empty::<Result<Bytes, std::io::Error>>()))
+}
+
+#[route("/videos/stream/{key:.*}", method = "GET", method = "HEAD")]
+async fn stream_video(
+    state: Data<AppState>,
+    path: Path<String>,
+    query: Query<StreamQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let decoded_key = decode_key(&path.into_inner(), &state.delimiter)?;
+    stream_object(&state, &state.bucket, &decoded_key, &query, &req).await
+}
+
+#[route("/buckets/{bucket}/videos/stream/{key:.*}", method = "GET", method = "HEAD")]
+async fn stream_bucket_video(
+    state: Data<AppState>,
+    path: Path<(String, String)>,
+    query: Query<StreamQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let (bucket, raw_key) = path.into_inner();
+    let bucket = state.resolve_bucket(Some(&bucket))?;
+    let decoded_key = decode_key(&raw_key, &state.delimiter)?;
+    stream_object(&state, bucket, &decoded_key, &query, &req).await
+}
+
+/// Builds an `attachment` disposition with an ASCII `filename` fallback and
+/// an RFC 5987 `filename*` carrying the exact UTF-8 name.
+fn attachment_disposition(key: &str, delimiter: &str) -> String {
+    let filename = basename(key, delimiter);
+    let ascii_fallback: String = filename
+        .chars()
+        .map(|c| {
+            if c.is_ascii() && !c.is_ascii_control() && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!(
+        "attachment; filename=\"{ascii_fallback}\"; filename*=UTF-8''{}",
+        urlencoding::encode(filename)
+    )
+}
+
+#[get("/videos/download/{key:.*}")]
+async fn download_video(
+    state: Data<AppState>,
+    path: Path<String>,
+    query: Query<StreamQuery>,
+    req: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    let decoded_key = decode_key(&path.into_inner(), &state.delimiter)?;
+    let disposition = attachment_disposition(&decoded_key, &state.delimiter);
+
+    match state.stream_mode {
+        StreamMode::Redirect => {
+            redirect_to_presigned(&state, &state.bucket, &decoded_key, &query, Some(&disposition))
+                .await
+        }
+        StreamMode::Proxy if query.wants_json()? => Err(presigned_url_unavailable()),
+        StreamMode::Proxy => {
+            let version_id = query.version_id();
+            proxy_object(&state, &state.bucket, &decoded_key, version_id, &req, Some(&disposition))
+                .await
+        }
+    }
+}
+
+#[get("/videos/meta/{key:.*}")]
+async fn video_metadata(
+    state: Data<AppState>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let key = decode_key(&path.into_inner(), &state.delimiter)?;
+    Ok(metadata_json_response(&state, &object_metadata(&state, key).await?))
+}
+
+/// Looks up several objects at once, in request order. Keys that don't exist
+/// come back as `{ "key", "notFound": true }` instead of failing the batch.
+#[post("/videos/meta/batch")]
+async fn video_metadata_batch(
+    state: Data<AppState>,
+    keys: web::Json<Vec<String>>,
+) -> Result<HttpResponse, ApiError> {
+    let keys = keys.into_inner();
+    if keys.len() > MAX_METADATA_BATCH {
+        return Err(ApiError::bad_request(format!(
+            "At most {MAX_METADATA_BATCH} keys can be requested at once"
+        )));
+    }
+    for key in &keys {
+        validate_key(key, &state.delimiter)?;
+    }
+
+    let items: Vec<BatchMetadataItem> = stream::iter(keys)
+        .map(|key| {
+            let state = &state;
+            async move {
+                match object_metadata(state, key.clone()).await {
+                    Ok(metadata) => Ok(BatchMetadataItem::Found(Box::new(metadata))),
+                    Err(err) if err.status_code() == StatusCode::NOT_FOUND => {
+                        Ok(BatchMetadataItem::NotFound { key, not_found: true })
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+        })
+        .buffered(METADATA_BATCH_CONCURRENCY)
+        .try_collect()
+        .await?;
+
+    Ok(metadata_json_response(&state, &items))
+}
+
+/// Deletes an object from the default bucket. S3 deletes are idempotent, so
+/// the object is looked up first to report missing keys as 404.
+#[delete("/videos/{key:.*}")]
+async fn delete_video(
+    req: HttpRequest,
+    state: Data<AppState>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_admin(&req)?;
+    let key = decode_key(&path.into_inner(), &state.delimiter)?;
+    head_object(&state, &key).await?;
+
+    let s3_key = state.s3_key(&key);
+    let delete = state.retry.run("delete_object", || {
+        state.s3.delete_object().bucket(&state.bucket).key(&s3_key).send()
+    });
+    state
+        .time_s3("delete_object", &state.bucket, &s3_key, delete)
+        .await
+        .map_err(|err| {
+            state.metrics.record_s3_failure("delete_object");
+            ApiError::from_s3("Failed to delete object", err)
+        })?;
+
+    state.invalidate_listings(&state.bucket, &key);
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Lists every version of one key, newest first. Buckets that never had
+/// versioning enabled report a single current version with ID `null`.
+#[get("/videos/versions/{key:.*}")]
+async fn video_versions(
+    state: Data<AppState>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let key = decode_key(&path.into_inner(), &state.delimiter)?;
+    let s3_key = state.s3_key(&key);
+    let mut versions = Vec::new();
+    let mut key_marker: Option<String> = None;
+    let mut version_id_marker: Option<String> = None;
+
+    loop {
+        let list = state.retry.run("list_object_versions", || {
+            state
+                .s3
+                .list_object_versions()
+                .bucket(&state.bucket)
+                .prefix(&s3_key)
+                .set_key_marker(key_marker.clone())
+                .set_version_id_marker(version_id_marker.clone())
+                .set_request_payer(state.request_payer.clone())
+                .send()
+        });
+        let response = state
+            .time_s3("list_object_versions", &state.bucket, &s3_key, list)
+            .await
+            .map_err(|err| {
+                state.metrics.record_s3_failure("list_object_versions");
+                ApiError::from_s3("Failed to list object versions", err)
+            })?;
+
+        // The prefix also matches longer keys such as `clip.mp4.bak`.
+        versions.extend(
+            response
+                .versions()
+                .iter()
+                .filter(|version| version.key() == Some(s3_key.as_str()))
+                .map(|version| ObjectVersionItem {
+                    version_id: version.version_id().map(str::to_string),
+                    size: version.size(),
+                    last_modified: version.last_modified().map(|dt| dt.to_string()),
+                    is_latest: version.is_latest().unwrap_or(false),
+                    delete_marker: false,
+                    modified_at: version.last_modified().cloned(),
+                }),
+        );
+        versions.extend(
+            response
+                .delete_markers()
+                .iter()
+                .filter(|marker| marker.key() == Some(s3_key.as_str()))
+                .map(|marker| ObjectVersionItem {
+                    version_id: marker.version_id().map(str::to_string),
+                    size: None,
+                    last_modified: marker.last_modified().map(|dt| dt.to_string()),
+                    is_latest: marker.is_latest().unwrap_or(false),
+                    delete_marker: true,
+                    modified_at: marker.last_modified().cloned(),
+                }),
+        );
+
+        if !response.is_truncated().unwrap_or(false) {
+            break;
+        }
+        key_marker = response.next_key_marker().map(str::to_string);
+        version_id_marker = response.next_version_id_marker().map(str::to_string);
+        // Stop once the listing has moved past our key.
+        if key_marker.as_deref().is_none_or(|marker| marker > s3_key.as_str()) {
+            break;
+        }
+    }
+
+    if versions.is_empty() {
+        return Err(ApiError::not_found(format!("No versions of {key:?} found")));
+    }
+    versions.sort_by_key(|v| std::cmp::Reverse(v.modified_at));
+    Ok(metadata_json_response(
+        &state,
+        &ObjectVersionsResponse { key, versions },
+    ))
+}
+
+async fn object_metadata(state: &AppState, key: String) -> Result<ObjectMetadata, ApiError> {
+    // Tags are only worth asking for once the object is known to exist, so a
+    // missing key costs one request and logs no tagging failure. They are
+    // then read while ffprobe runs.
+    let head = head_object(state, &key).await?;
+    let (tags, media) = future::join(
+        object_tags(state, &state.bucket, &key),
+        probe_media(state, &key, head.e_tag(), head.content_type()),
+    )
+    .await;
+
+    Ok(ObjectMetadata {
+        content_type: head.content_type().map(str::to_string),
+        content_length: head.content_length(),
+        last_modified: head.last_modified().map(|dt| dt.to_string()),
+        e_tag: head.e_tag().map(str::to_string),
+        metadata: head.metadata().cloned().unwrap_or_default(),
+        tags,
+        storage_class: head.storage_class().map(|class| class.as_str().to_string()),
+        restore_status: RestoreStatus::from_header(head.restore()),
+        media,
+        key,
+    })
+}
+
+async fn health(state: Data<AppState>) -> HttpResponse {
+    match &state.health_body {
+        Some(body) => HttpResponse::Ok()
+            .content_type(header::ContentType::plaintext())
+            .body(body.clone()),
+        None => HttpResponse::Ok().json(HealthResponse { status: "ok" }),
+    }
+}
+
+/// The liveness route at `path`. The default `/api/health` is registered in
+/// the API scope as it always was; a custom `HEALTH_PATH` is registered ahead
+/// of it, so it answers even inside `/api` without the API middleware.
+fn health_route(path: &str) -> actix_web::Resource {
+    web::resource(path).route(web::get().to(health))
+}
+
+/// `HeadBucket` with `READINESS_TIMEOUT` in place of the client-wide timeouts.
+async fn probe_bucket(
+    s3: &Client,
+    bucket: &str,
+) -> Result<HeadBucketOutput, SdkError<HeadBucketError>> {
+    s3.head_bucket()
+        .bucket(bucket)
+        .customize()
+        .config_override(
+            aws_sdk_s3::config::Builder::default().timeout_config(
+                TimeoutConfig::builder()
+                    .connect_timeout(READINESS_TIMEOUT)
+                    .operation_timeout(READINESS_TIMEOUT)
+                    .build(),
+            ),
+        )
+        .send()
+        .await
+}
+
+#[get("/ready")]
+async fn ready(state: Data<AppState>) -> impl Responder {
+    let started = Instant::now();
+    let probe = probe_bucket(&state.s3, &state.bucket).await;
+    let latency_ms = started.elapsed().as_millis();
+
+    let error = match probe {
+        Ok(_) => None,
+        Err(err) => {
+            state.metrics.record_s3_failure("head_bucket");
+            if error::is_timeout(&err) {
+                Some(format!(
+                    "Bucket check timed out after {}ms",
+                    READINESS_TIMEOUT.as_millis()
+                ))
+            } else {
+                Some(format!("Bucket check failed: {err}"))
+            }
+        }
+    };
+
+    let mut response = if error.is_none() {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    response.json(ReadinessResponse {
+        status: if error.is_none() { "ready" } else { "unavailable" },
+        bucket: state.bucket.clone(),
+        latency_ms,
+        error,
+    })
+}
+
+/// Logs panics through tracing instead of bare stderr, with a backtrace and
+/// the thread name (`actix-server worker N` for request handling). A panic
+/// while serving happens inside the request span, so the log line carries
+/// its `request_id`; proxied bodies keep the span while they stream.
+fn install_panic_hook() {
+    std::panic::set_hook(Box::new(|info| {
+        let backtrace = Backtrace::force_capture();
+        let thread = std::thread::current();
+        let thread = thread.name().unwrap_or("<unnamed>");
+        tracing::error!(thread, "{info}\n{backtrace}");
+    }));
+}
+
+/// Resolves on SIGTERM or Ctrl-C, whichever arrives first.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use actix_web::rt::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                futures_util::future::select(
+                    Box::pin(terminate.recv()),
+                    Box::pin(actix_web::rt::signal::ctrl_c()),
+                )
+                .await;
+            }
+            Err(err) => {
+                tracing::warn!("Failed to install SIGTERM handler: {err}");
+                let _ = actix_web::rt::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = actix_web::rt::signal::ctrl_c().await;
+    }
+}
+
+/// Names the threads this process creates itself, so panics and thread dumps
+/// say where they came from. Request handlers run on actix's own
+/// `actix-server worker N` threads, whose names actix fixes. The name comes
+/// from the config, so it is loaded before the runtime starts.
+fn main() -> Result<()> {
+    let _ = dotenvy::dotenv();
+
+    let config = load_config()?;
+    config.validate()?;
+    let thread_name = config.blocking_thread_name.clone();
+    actix_web::rt::System::with_tokio_rt(move || {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .thread_name(thread_name)
+            .build()
+            .expect("failed to build the main runtime")
+    })
+    .block_on(run(config))
+}
+
+async fn run(config: AppConfig) -> Result<()> {
+    let _access_log_guard =
+        access_log::init_tracing(config.access_log_dir.as_deref(), config.log_format);
+    install_panic_hook();
+
+    let s3_client = build_s3_client(&config, config.aws_s3_endpoint_url.as_deref()).await?;
+    let s3_fallback = match &config.aws_s3_endpoint_url_fallback {
+        Some(endpoint_url) => Some(build_s3_client(&config, Some(endpoint_url)).await?),
+        None => None,
+    };
+    if config.startup_check {
+        for bucket in &config.aws_s3_bucket_names {
+            probe_bucket(&s3_client, bucket).await.map_err(|err| {
+                let reason = if error::is_timeout(&err) {
+                    format!("timed out after {}ms", READINESS_TIMEOUT.as_millis())
+                } else {
+                    aws_sdk_s3::error::DisplayErrorContext(&err).to_string()
+                };
+                anyhow::anyhow!("Startup check failed: bucket {bucket:?} is unreachable: {reason}")
+            })?;
+        }
+        tracing::info!("Startup check passed for {} bucket(s)", config.aws_s3_bucket_names.len());
+    }
+    let metrics = Data::new(Metrics::new().context("Failed to register metrics")?);
+    let api_key = Data::new(ApiKey(config.api_key.clone()));
+    let rate_limiter = Data::new(RateLimiter::new(config.rate_limit, config.trust_proxy));
+    let maintenance = Data::new(Maintenance {
+        enabled: config.maintenance_mode,
+        file: config.maintenance_file.clone(),
+    });
+    let session_auth = Data::new(SessionAuth(config.session.clone()));
+    let state = Data::new(AppState::new(&config, s3_client, s3_fallback, metrics.clone()));
+
+    let event_hub = Data::new(EventHub::new());
+    actix_web::rt::spawn(events::poll_changes(
+        state.clone(),
+        event_hub.clone(),
+        Duration::from_secs(config.events_poll_seconds),
+    ));
+
+    if config.index_mode {
+        actix_web::rt::spawn(index::refresh_index(
+            state.clone(),
+            Duration::from_secs(config.index_refresh_seconds),
+        ));
+    }
+
+    let bind_addr = config.bind_addr;
+    let shutdown_timeout_seconds = config.shutdown_timeout_seconds;
+    let workers = config.workers;
+    let keep_alive = match config.keep_alive_seconds {
+        0 => KeepAlive::Disabled,
+        seconds => KeepAlive::Timeout(Duration::from_secs(seconds)),
+    };
+    tracing::info!(
+        workers,
+        keep_alive_seconds = config.keep_alive_seconds,
+        "Starting HTTP server"
+    );
+    let tls_config = config
+        .tls
+        .as_ref()
+        .map(tls::load_server_config)
+        .transpose()?;
+
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .app_data(metrics.clone())
+            .app_data(api_key.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(maintenance.clone())
+            .app_data(session_auth.clone())
+            .app_data(event_hub.clone())
+            .app_data(web::QueryConfig::default().error_handler(|err, _| {
+                ApiError::bad_request(err.to_string()).into()
+            }))
+            .app_data(web::JsonConfig::default().error_handler(|err, _| {
+                ApiError::bad_request(err.to_string()).into()
+            }))
+            .wrap(from_fn(request_id::assign_request_id))
+            .wrap(Logger::new(ACCESS_LOG_FORMAT))
+            .wrap(from_fn(access_log::log_access))
+            .service(
+                web::scope(&config.base_path)
+                    .configure(|cfg| {
+                        if config.health_path != DEFAULT_HEALTH_PATH {
+                            cfg.service(health_route(&config.health_path));
+                        }
+                    })
+                    .service(
+                        web::scope("/api")
+                            .wrap(from_fn(auth::require_auth))
+                            .wrap(from_fn(metrics::track_requests))
+                            .wrap(from_fn(server_timing::add_server_timing))
+                            .wrap(from_fn(error::expose_s3_errors))
+                            .wrap(from_fn(rate_limit::limit_requests))
+                            .wrap(from_fn(maintenance::check_maintenance))
+                            .wrap(build_cors(&config.allowed_origins))
+                            .wrap(Compress::default())
+                            .configure(|cfg| {
+                                if config.health_path == DEFAULT_HEALTH_PATH {
+                                    cfg.service(health_route("/health"));
+                                }
+                            })
+                            .service(ready)
+                            .service(list_videos)
+                            .service(count_videos)
+                            .service(random_video)
+                            .service(list_objects)
+                            .service(stream_video)
+                            .service(stream_bucket_video)
+                            .service(video_metadata_batch)
+                            .service(video_metadata)
+                            .service(video_versions)
+                            .service(download_video)
+                            .service(upload::upload_url)
+                            .service(upload::multipart_start)
+                            .service(upload::multipart_sign)
+                            .service(upload::multipart_complete)
+                            .service(upload::multipart_abort)
+                            .service(delete_video)
+                            .service(restore::restore_video)
+                            .service(thumbnail::video_thumbnail)
+                            .service(sprite::video_sprite)
+                            .service(hls::hls_playlist)
+                            .service(subtitles::video_subtitle)
+                            .service(events::events)
+                            .service(index::reindex)
+                            .service(session::login)
+                            .service(session::logout),
+                    )
+                    .service(metrics::metrics_endpoint)
+                    .service(static_files(&config.static_dir, config.spa_fallback)),
+            )
+            .default_service(web::to(not_found))
+    })
+    .workers(workers)
+    .keep_alive(keep_alive)
+    .shutdown_timeout(shutdown_timeout_seconds)
+    .disable_signals();
+
+    let server = match tls_config {
+        Some(tls_config) => {
+            tracing::info!("Serving HTTPS on {bind_addr}");
+            server.bind_rustls_0_23(bind_addr, tls_config)?
+        }
+        None => {
+            tracing::info!("Serving HTTP on {bind_addr}");
+            server.bind(bind_addr)?
+        }
+    }
+    .run();
+
+    let handle = server.handle();
+    actix_web::rt::spawn(async move {
+        shutdown_signal().await;
+        tracing::info!(
+            "Shutdown requested, draining in-flight requests for up to {shutdown_timeout_seconds}s"
+        );
+        handle.stop(true).await;
+    });
+
+    server.await?;
+    tracing::info!("Server stopped, connections drained");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{
+        dev::ServiceResponse,
+        http::StatusCode,
+        test::{call_service, init_service, read_body, read_body_json, TestRequest},
+    };
+
+    use super::*;
+    use crate::mock_s3::{list_page, sized_list_page, MockS3, OBJECT_SIZE};
+
+    fn test_config(vars: &[(&str, &str)]) -> AppConfig {
+        let mut env: HashMap<String, String> = [
+            ("AWS_REGION", "us-east-1"),
+            ("AWS_S3_BUCKET_NAME", "bucket"),
+        ]
+        .into_iter()
+        .chain(vars.iter().copied())
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        env.retain(|_, value| !value.is_empty());
+        load_config_from(&ConfigSource {
+            env,
+            file: HashMap::new(),
+        })
+        .unwrap()
+    }
+
+    fn test_state(s3: &MockS3, vars: &[(&str, &str)]) -> Data<AppState> {
+        let metrics = Data::new(Metrics::new().unwrap());
+        Data::new(AppState::new(&test_config(vars), s3.client(), None, metrics))
+    }
+
+    /// State for tests that never reach S3.
+    fn offline_state(vars: &[(&str, &str)]) -> Data<AppState> {
+        test_state(&MockS3::new(|_, _| (500, String::new())), vars)
+    }
+
+    async fn get(state: &Data<AppState>, req: TestRequest) -> ServiceResponse {
+        let app = init_service(
+            App::new()
+                .app_data(state.clone())
+                .service(list_videos)
+                .service(stream_video),
+        )
+        .await;
+        call_service(&app, req.to_request()).await
+    }
+
+    async fn get_json(state: &Data<AppState>, uri: &str) -> serde_json::Value {
+        let response = get(state, TestRequest::get().uri(uri)).await;
+        assert_eq!(response.status(), StatusCode::OK, "GET {uri}");
+        read_body_json(response).await
+    }
+
+    #[actix_web::test]
+    async fn listing_follows_continuation_tokens_across_pages() {
+        let s3 = MockS3::new(|_, uri| {
+            if uri.contains("continuation-token=page2") {
+                (200, list_page(&["c.mp4"], None))
+            } else {
+                (200, list_page(&["a.mp4", "b.mp4"], Some("page2")))
+            }
+        });
+        let state = test_state(&s3, &[]);
+
+        let first = get_json(&state, "/videos?pageSize=2").await;
+        assert_eq!(s3.requests().len(), 2);
+        assert!(s3.requests()[1].contains("continuation-token=page2"));
+        assert_eq!(first["videos"].as_array().unwrap().len(), 2);
+        assert_eq!(first["pagination"]["totalVideos"], 3);
+        assert_eq!(first["pagination"]["totalPages"], 2);
+        assert_eq!(first["pagination"]["hasNextPage"], true);
+        assert_eq!(first["pagination"]["hasPrevPage"], false);
+
+        let second = get_json(&state, "/videos?pageSize=2&page=2").await;
+        assert_eq!(second["videos"][0]["key"], "c.mp4");
+        assert_eq!(second["pagination"]["hasNextPage"], false);
+        assert_eq!(second["pagination"]["hasPrevPage"], true);
+    }
+
+    fn video(key: &str, size: i64, modified_secs: Option<i64>) -> VideoItem {
+        VideoItem {
+            id: object_id(key, None),
+            key: key.to_string(),
+            title: key.to_string(),
+            size,
+            last_modified: None,
+            stream_url: String::new(),
+            thumbnail_url: None,
+            storage_class: None,
+            modified_at: modified_secs.map(DateTime::from_secs),
+            subtitles: Vec::new(),
+            tags: None,
+        }
+    }
+
+    fn sorted_keys(field: SortField, descending: bool) -> Vec<String> {
+        let mut videos = vec![
+            video("b.mp4", 30, Some(200)),
+            video("undated.mp4", 20, None),
+            video("a.mp4", 10, Some(300)),
+            video("c.mp4", 20, Some(100)),
+        ];
+        sort_videos(&mut videos, field, descending);
+        videos.into_iter().map(|video| video.key).collect()
+    }
+
+    #[test]
+    fn sort_videos_orders_by_each_field_in_both_directions() {
+        assert_eq!(
+            sorted_keys(SortField::Name, false),
+            ["a.mp4", "b.mp4", "c.mp4", "undated.mp4"]
+        );
+        assert_eq!(
+            sorted_keys(SortField::Name, true),
+            ["undated.mp4", "c.mp4", "b.mp4", "a.mp4"]
+        );
+        assert_eq!(
+            sorted_keys(SortField::Size, false),
+            ["a.mp4", "c.mp4", "undated.mp4", "b.mp4"]
+        );
+        assert_eq!(
+            sorted_keys(SortField::Size, true),
+            ["b.mp4", "c.mp4", "undated.mp4", "a.mp4"]
+        );
+    }
+
+    #[test]
+    fn sort_videos_puts_undated_videos_last_in_either_direction() {
+        assert_eq!(
+            sorted_keys(SortField::Date, false),
+            ["c.mp4", "b.mp4", "a.mp4", "undated.mp4"]
+        );
+        assert_eq!(
+            sorted_keys(SortField::Date, true),
+            ["a.mp4", "b.mp4", "c.mp4", "undated.mp4"]
+        );
+    }
+
+    #[test]
+    fn sort_field_parses_case_insensitively_and_defaults_to_name() {
+        assert!(SortField::from_query(Some("SIZE")) == SortField::Size);
+        assert!(SortField::from_query(Some("date")) == SortField::Date);
+        assert!(SortField::from_query(Some("bogus")) == SortField::Name);
+        assert!(SortField::from_query(None) == SortField::Name);
+    }
+
+    #[actix_web::test]
+    async fn custom_extension_list_replaces_the_defaults() {
+        assert_eq!(
+            parse_extensions_env(Some(" MKV, .webm ,,".to_string())),
+            [".mkv", ".webm"]
+        );
+        assert_eq!(
+            parse_extensions_env(Some(" , ".to_string())),
+            parse_extensions_env(None)
+        );
+
+        let s3 = MockS3::new(|_, _| (200, list_page(&["a.MKV", "b.webm", "c.mp4", "mkv"], None)));
+        let state = test_state(&s3, &[("VIDEO_EXTENSIONS", "mkv,.webm")]);
+        let listing = get_json(&state, "/videos").await;
+        let keys: Vec<_> = listing["videos"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|video| video["key"].as_str().unwrap())
+            .collect();
+        assert_eq!(keys, ["a.MKV", "b.webm"]);
+    }
+
+    #[actix_web::test]
+    async fn listing_within_the_cache_ttl_skips_s3() {
+        let s3 = MockS3::new(|_, _| (200, list_page(&["a.mp4"], None)));
+        let state = test_state(&s3, &[("LIST_CACHE_TTL_SECONDS", "60")]);
+
+        get_json(&state, "/videos").await;
+        let cached = get_json(&state, "/videos?page=1&sort=size").await;
+        assert_eq!(cached["videos"][0]["key"], "a.mp4");
+        assert_eq!(s3.requests().len(), 1);
+
+        get_json(&state, "/videos?prefix=shows/").await;
+        assert_eq!(s3.requests().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn folder_counts_are_cached_with_the_listing() {
+        let s3 = MockS3::with_keys(&NESTED_KEYS);
+        let state = test_state(&s3, &[("LIST_CACHE_TTL_SECONDS", "60")]);
+
+        let first = get_json(&state, "/videos").await;
+        assert_eq!(first["folders"][0]["videoCount"], 1);
+        let requests = s3.requests().len();
+        let second = get_json(&state, "/videos?sort=size").await;
+        assert_eq!(second["folders"][0]["videoCount"], 1);
+        assert_eq!(s3.requests().len(), requests);
+    }
+
+    #[actix_web::test]
+    async fn listing_without_a_cache_ttl_always_asks_s3() {
+        let s3 = MockS3::new(|_, _| (200, list_page(&["a.mp4"], None)));
+        let state = test_state(&s3, &[]);
+
+        get_json(&state, "/videos").await;
+        get_json(&state, "/videos").await;
+        assert_eq!(s3.requests().len(), 2);
+    }
+
+    async fn cors_allow_origin(origin: &str) -> Option<String> {
+        let vars = [("ALLOWED_ORIGINS", "https://a.example")];
+        let state = test_state(&MockS3::new(|_, _| (200, list_page(&[], None))), &vars);
+        let app = init_service(
+            App::new().app_data(state).service(
+                web::scope("/api")
+                    .wrap(build_cors(&test_config(&vars).allowed_origins))
+                    .service(list_videos),
+            ),
+        )
+        .await;
+        let req = TestRequest::get()
+            .uri("/api/videos")
+            .insert_header((header::ORIGIN, origin))
+            .to_request();
+        let response = call_service(&app, req).await;
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[actix_web::test]
+    async fn cors_echoes_allowed_origins_only() {
+        assert_eq!(
+            cors_allow_origin("https://a.example").await.as_deref(),
+            Some("https://a.example")
+        );
+        assert_eq!(cors_allow_origin("https://b.example").await, None);
+    }
+
+    fn keys(listing: &serde_json::Value, field: &str) -> Vec<String> {
+        listing[field]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item.get("key").unwrap_or(&item["prefix"]).as_str().unwrap().to_string())
+            .collect()
+    }
+
+    const NESTED_KEYS: [&str; 5] = [
+        "a.mp4",
+        "notes.txt",
+        "shows/e00.mp4",
+        "shows/s01/e01.mp4",
+        "shows/s01/e02.mp4",
+    ];
+
+    #[actix_web::test]
+    async fn recursive_listing_flattens_nested_folders() {
+        let state = test_state(&MockS3::with_keys(&NESTED_KEYS), &[]);
+
+        let folder = get_json(&state, "/videos").await;
+        assert_eq!(keys(&folder, "videos"), ["a.mp4"]);
+        assert_eq!(keys(&folder, "folders"), ["shows/"]);
+
+        let all = get_json(&state, "/videos?recursive=true").await;
+        assert_eq!(
+            keys(&all, "videos"),
+            ["a.mp4", "shows/e00.mp4", "shows/s01/e01.mp4", "shows/s01/e02.mp4"]
+        );
+        assert!(keys(&all, "folders").is_empty());
+
+        let shows = get_json(&state, "/videos?recursive=true&prefix=shows").await;
+        assert_eq!(
+            keys(&shows, "videos"),
+            ["shows/e00.mp4", "shows/s01/e01.mp4", "shows/s01/e02.mp4"]
+        );
+        assert_eq!(shows["pagination"]["totalVideos"], 3);
+    }
+
+    #[actix_web::test]
+    async fn search_matches_the_basename_case_insensitively() {
+        let state = test_state(&MockS3::with_keys(&["shows/S01/Episode 1.mp4"]), &[]);
+        let matches = |search: &'static str| {
+            let state = state.clone();
+            async move {
+                let uri = format!("/videos?recursive=true&search={search}");
+                get_json(&state, &uri).await["pagination"]["totalVideos"] == 1
+            }
+        };
+        assert!(matches("episode").await);
+        assert!(matches("%20%20ODE%201%20%20").await);
+        assert!(!matches("s01").await);
+        assert!(!matches("shows").await);
+        assert!(matches("%20%20%20").await);
+    }
+
+    #[actix_web::test]
+    async fn search_paginates_over_the_matching_videos_only() {
+        let state = test_state(&MockS3::with_keys(&NESTED_KEYS), &[]);
+
+        let listing = get_json(&state, "/videos?recursive=true&search=e0&pageSize=2").await;
+        assert_eq!(keys(&listing, "videos"), ["shows/e00.mp4", "shows/s01/e01.mp4"]);
+        assert_eq!(listing["pagination"]["totalVideos"], 3);
+        assert_eq!(listing["pagination"]["totalPages"], 2);
+        assert_eq!(listing["pagination"]["totalBytes"], 3 * 1024);
+
+        let listing = get_json(&state, "/videos?recursive=true&search=s01").await;
+        assert_eq!(listing["pagination"]["totalVideos"], 0);
+    }
+
+    #[test]
+    fn environment_overrides_the_config_file() {
+        let file = ConfigSource::parse_file(
+            r#"
+            aws_region = "eu-west-1"
+            aws_s3_bucket_name = "from-file"
+            video_extensions = ["mkv", "webm"]
+            port = 8080
+            "#,
+        )
+        .unwrap();
+        let env = HashMap::from([("AWS_S3_BUCKET_NAME".to_string(), "from-env".to_string())]);
+        let config = load_config_from(&ConfigSource { env, file }).unwrap();
+
+        assert_eq!(config.aws_s3_bucket_name, "from-env");
+        assert_eq!(config.aws_region, "eu-west-1");
+        assert_eq!(config.video_extensions, [".mkv", ".webm"]);
+        assert_eq!(config.bind_addr.port(), 8080);
+    }
+
+    #[test]
+    fn malformed_config_file_is_an_error() {
+        assert!(ConfigSource::parse_file("port = ").is_err());
+    }
+
+    #[actix_web::test]
+    async fn date_range_bounds_are_exclusive_and_either_may_be_open() {
+        // Every mocked object was last modified at 2024-01-01T00:00:00Z.
+        let state = test_state(&MockS3::with_keys(&["a.mp4"]), &[]);
+        for (query, expected) in [
+            ("modifiedAfter=2023-12-31T00:00:00Z", 1),
+            ("modifiedAfter=2024-01-01T00:00:00Z", 0),
+            ("modifiedBefore=2024-01-01T00:00:01Z", 1),
+            ("modifiedBefore=2024-01-01T00:00:00Z", 0),
+            ("modifiedAfter=2023-01-01T00:00:00Z&modifiedBefore=2025-01-01T00:00:00Z", 1),
+            ("modifiedAfter=", 1),
+        ] {
+            let listing = get_json(&state, &format!("/videos?{query}")).await;
+            assert_eq!(listing["pagination"]["totalVideos"], expected, "{query}");
+        }
+    }
+
+    #[actix_web::test]
+    async fn invalid_timestamps_are_rejected() {
+        assert!(parse_timestamp_param("modifiedAfter", Some("yesterday")).is_err());
+        assert!(parse_timestamp_param("modifiedBefore", Some("2024-13-01T00:00:00Z")).is_err());
+        assert!(parse_timestamp_param("modifiedAfter", Some("")).unwrap().is_none());
+
+        let state = test_state(&MockS3::with_keys(&["a.mp4"]), &[]);
+        let response = get(&state, TestRequest::get().uri("/videos?modifiedAfter=2024-01-01")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    async fn size_keys(query: &str) -> Vec<String> {
+        let objects = [("0.mp4", 0), ("100.mp4", 100), ("500.mp4", 500), ("1000.mp4", 1000)];
+        let s3 = MockS3::new(move |_, _| (200, sized_list_page(&objects, None)));
+        let listing = get_json(&test_state(&s3, &[]), &format!("/videos?sort=size&{query}")).await;
+        keys(&listing, "videos")
+    }
+
+    #[actix_web::test]
+    async fn size_bounds_are_inclusive_and_combine() {
+        assert_eq!(size_keys("").await, ["0.mp4", "100.mp4", "500.mp4", "1000.mp4"]);
+        assert_eq!(size_keys("minSize=500").await, ["500.mp4", "1000.mp4"]);
+        assert_eq!(size_keys("maxSize=500").await, ["0.mp4", "100.mp4", "500.mp4"]);
+        assert_eq!(size_keys("minSize=100&maxSize=500").await, ["100.mp4", "500.mp4"]);
+        assert!(size_keys("minSize=600&maxSize=500").await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn any_lower_bound_hides_empty_placeholders() {
+        assert_eq!(size_keys("minSize=0").await, ["100.mp4", "500.mp4", "1000.mp4"]);
+    }
+
+    #[test]
+    fn session_token_is_passed_to_the_credentials() {
+        let config = test_config(&[
+            ("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+            ("AWS_SESSION_TOKEN", "token"),
+        ]);
+        let credentials = static_credentials(&config).unwrap();
+        assert_eq!(credentials.access_key_id(), "AKIDEXAMPLE");
+        assert_eq!(credentials.secret_access_key(), "secret");
+        assert_eq!(credentials.session_token(), Some("token"));
+
+        let config = test_config(&[
+            ("AWS_ACCESS_KEY_ID", "AKIDEXAMPLE"),
+            ("AWS_SECRET_ACCESS_KEY", "secret"),
+        ]);
+        assert_eq!(static_credentials(&config).unwrap().session_token(), None);
+        assert!(static_credentials(&test_config(&[])).is_none());
+    }
+
+    #[actix_web::test]
+    async fn malicious_keys_are_rejected() {
+        for key in ["..", "../etc/passwd", "a/../../b.mp4", "/etc/passwd", "a\0b.mp4", "a\r\nb"] {
+            assert!(validate_key(key, "/").is_err(), "{key:?}");
+        }
+        for key in ["a..b/clip.mp4", "...mp4", "shows/.hidden/clip.mp4", "caf\u{e9}.mp4"] {
+            assert!(validate_key(key, "/").is_ok(), "{key:?}");
+        }
+
+        let state = offline_state(&[]);
+        let uri = "/videos/stream/a%2F..%2F..%2Fb.mp4";
+        let response = get(&state, TestRequest::get().uri(uri)).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn page_size_defaults_on_zero_and_clamps_to_the_maximum() {
+        let state = offline_state(&[("DEFAULT_PAGE_SIZE", "10"), ("MAX_PAGE_SIZE", "50")]);
+        assert_eq!(state.page_size(None), 10);
+        assert_eq!(state.page_size(Some(0)), 10);
+        assert_eq!(state.page_size(Some(25)), 25);
+        assert_eq!(state.page_size(Some(10_000)), 50);
+
+        let s3 = MockS3::with_keys(&["a.mp4"]);
+        let state = test_state(&s3, &[("MAX_PAGE_SIZE", "50")]);
+        let listing = get_json(&state, "/videos?pageSize=500").await;
+        assert_eq!(listing["pageSize"], 50);
+        assert_eq!(listing["pagination"]["pageSize"], 50);
+    }
+
+    async fn content_encoding(accept_encoding: Option<&str>) -> Option<String> {
+        let keys: Vec<String> = (0..50).map(|i| format!("episode-{i:02}.mp4")).collect();
+        let keys: Vec<&str> = keys.iter().map(String::as_str).collect();
+        let state = test_state(&MockS3::with_keys(&keys), &[]);
+        let app = init_service(
+            App::new()
+                .app_data(state)
+                .service(web::scope("/api").wrap(Compress::default()).service(list_videos)),
+        )
+        .await;
+        let mut req = TestRequest::get().uri("/api/videos");
+        if let Some(accept_encoding) = accept_encoding {
+            req = req.insert_header((header::ACCEPT_ENCODING, accept_encoding));
+        }
+        let response = call_service(&app, req.to_request()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        response
+            .headers()
+            .get(header::CONTENT_ENCODING)
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[actix_web::test]
+    async fn listings_are_gzipped_for_clients_that_accept_it() {
+        assert_eq!(content_encoding(Some("gzip")).await.as_deref(), Some("gzip"));
+        assert_eq!(content_encoding(None).await, None);
+    }
+
+    #[actix_web::test]
+    async fn ext_filter_keeps_only_that_extension() {
+        let s3 = MockS3::with_keys(&["a.mp4", "b.mov", "c.mkv", "d.MOV"]);
+        let state = test_state(&s3, &[]);
+        let listing = get_json(&state, "/videos?ext=mov").await;
+        assert_eq!(keys(&listing, "videos"), ["b.mov", "d.MOV"]);
+        assert_eq!(listing["pagination"]["totalVideos"], 2);
+        let listing = get_json(&state, "/videos?ext=.MKV").await;
+        assert_eq!(keys(&listing, "videos"), ["c.mkv"]);
+
+        for ext in ["exe", "txt"] {
+            let response = get(&state, TestRequest::get().uri(&format!("/videos?ext={ext}"))).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{ext}");
+        }
+    }
+
+    #[test]
+    fn range_headers_follow_the_bytes_grammar() {
+        for range in ["bytes=0-99", "bytes=100-", "bytes=-500", "bytes=0-1, 5-9", " bytes=7-7"] {
+            assert!(is_valid_range(range), "{range:?}");
+        }
+        for range in ["bytes=9-1", "bytes=", "bytes=-", "bytes=a-b", "items=0-1", "0-1", "bytes=0-1,"] {
+            assert!(!is_valid_range(range), "{range:?}");
+        }
+    }
+
+    async fn proxied(range: &str) -> ServiceResponse {
+        let state = test_state(&MockS3::with_keys(&["clip.mp4"]), &[("STREAM_MODE", "proxy")]);
+        let req = TestRequest::get()
+            .uri("/videos/stream/clip.mp4")
+            .insert_header((header::RANGE, range));
+        get(&state, req).await
+    }
+
+    fn header_value(response: &ServiceResponse, name: header::HeaderName) -> &str {
+        response.headers().get(name).unwrap().to_str().unwrap()
+    }
+
+    #[actix_web::test]
+    async fn proxied_ranges_are_forwarded_to_s3() {
+        let response = proxied("bytes=0-99").await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(header_value(&response, header::CONTENT_RANGE), "bytes 0-99/1024");
+    }
+
+    #[actix_web::test]
+    async fn range_past_the_end_is_not_satisfiable() {
+        let response = proxied("bytes=5000-").await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(
+            header_value(&response, header::CONTENT_RANGE),
+            format!("bytes */{OBJECT_SIZE}")
+        );
+    }
+
+    #[actix_web::test]
+    async fn malformed_range_gets_the_whole_object() {
+        let response = proxied("bytes=9-1").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(read_body(response).await.len() as u64, OBJECT_SIZE);
+    }
+
+    #[actix_web::test]
+    async fn verify_before_presign_turns_missing_keys_into_404() {
+        let s3 = MockS3::with_keys(&["clip.mp4"]);
+        let state = test_state(&s3, &[("VERIFY_BEFORE_PRESIGN", "true")]);
+
+        let missing = get(&state, TestRequest::get().uri("/videos/stream/gone.mp4")).await;
+        assert_eq!(missing.status(), StatusCode::NOT_FOUND);
+
+        let found = get(&state, TestRequest::get().uri("/videos/stream/clip.mp4")).await;
+        assert!(found.status().is_redirection());
+        assert!(header_value(&found, header::LOCATION).starts_with("http://s3.test/bucket/clip.mp4?"));
+        assert!(s3.requests().iter().all(|request| request.starts_with("HEAD ")));
+    }
+
+    #[actix_web::test]
+    async fn presigning_without_verification_redirects_missing_keys() {
+        let s3 = MockS3::with_keys(&[]);
+        let state = test_state(&s3, &[]);
+
+        let response = get(&state, TestRequest::get().uri("/videos/stream/gone.mp4")).await;
+        assert!(response.status().is_redirection());
+    }
+
+    #[actix_web::test]
+    async fn archived_objects_are_not_presigned() {
+        let s3 = MockS3::from_fn(|request| {
+            let mut response = crate::mock_s3::response(200, String::new());
+            if request.uri().ends_with("/frozen.mp4") {
+                response.headers_mut().insert("x-amz-storage-class", "GLACIER");
+            }
+            response
+        });
+        let state = test_state(&s3, &[]);
+
+        let frozen = get(&state, TestRequest::get().uri("/videos/stream/frozen.mp4")).await;
+        assert_eq!(frozen.status(), StatusCode::CONFLICT);
+        let thawed = get(&state, TestRequest::get().uri("/videos/stream/clip.mp4")).await;
+        assert!(thawed.status().is_redirection());
+    }
+
+    #[test]
+    fn normalize_prefix_appends_the_delimiter_unless_raw() {
+        let normalize = |prefix: &str, raw, delimiter| normalize_prefix(prefix.to_string(), raw, delimiter);
+        assert_eq!(normalize("", false, "/"), "");
+        assert_eq!(normalize("shows", false, "/"), "shows/");
+        assert_eq!(normalize("shows/", false, "/"), "shows/");
+        assert_eq!(normalize("shows/s0", true, "/"), "shows/s0");
+        assert_eq!(normalize("", true, "/"), "");
+        assert_eq!(normalize("shows", false, "::"), "shows::");
+        assert_eq!(normalize("shows::", false, "::"), "shows::");
+    }
+
+    #[actix_web::test]
+    async fn listed_prefix_is_normalized_unless_raw() {
+        let state = test_state(&MockS3::with_keys(&NESTED_KEYS), &[]);
+        let bare = get_json(&state, "/videos?prefix=shows").await;
+        let slashed = get_json(&state, "/videos?prefix=shows/").await;
+        assert_eq!(bare["prefix"], "shows/");
+        assert_eq!(bare, slashed);
+        assert_eq!(keys(&bare, "videos"), ["shows/e00.mp4"]);
+        assert_eq!(keys(&bare, "folders"), ["shows/s01/"]);
+
+        let listing = get_json(&state, "/videos?prefix=shows/s01/e0&rawPrefix=true").await;
+        assert_eq!(keys(&listing, "videos"), ["shows/s01/e01.mp4", "shows/s01/e02.mp4"]);
+        assert_eq!(listing["prefix"], "shows/s01/e0");
+    }
+
+    #[actix_web::test]
+    async fn stream_route_round_trips_listed_keys_exactly() {
+        let keys = [
+            "my clip.mp4",
+            "caf\u{e9}/na\u{ef}ve \u{65e5}\u{672c}.mp4",
+            "a+b.mp4",
+            "100%.mp4",
+            "literal%20space.mp4",
+            "dir/sub dir/x.mp4",
+        ];
+        let state = test_state(&MockS3::with_keys(&keys), &[("STREAM_MODE", "proxy")]);
+
+        for key in keys {
+            let url = stream_url("", None, key);
+            let uri = url.strip_prefix("/api").unwrap();
+            let response = get(&state, TestRequest::get().uri(uri)).await;
+            assert_eq!(response.status(), StatusCode::OK, "{key:?} via {uri}");
+        }
+        assert_eq!(decode_key("a+b%2Fc.mp4", "/").unwrap(), "a+b%2Fc.mp4");
+    }
+
+    #[test]
+    fn key_filter_denies_before_allowing_and_matches_whole_keys() {
+        let filter = |allow: &str, deny: &str| {
+            test_config(&[("KEY_ALLOW_REGEX", allow), ("KEY_DENY_REGEX", deny)]).key_filter
+        };
+
+        let no_tmp = filter("", "^tmp/");
+        assert!(!no_tmp.allows("tmp/upload.mp4"));
+        assert!(no_tmp.allows("shows/tmp/upload.mp4"));
+
+        let public_only = filter("^public/", r"\.partial\.mp4$");
+        assert!(public_only.allows("public/clip.mp4"));
+        assert!(!public_only.allows("private/public/clip.mp4"));
+        assert!(!public_only.allows("public/clip.partial.mp4"));
+
+        assert!(KeyFilter::default().allows("anything.mp4"));
+    }
+
+    #[test]
+    fn invalid_key_regex_fails_startup() {
+        let env = HashMap::from([
+            ("AWS_REGION".to_string(), "us-east-1".to_string()),
+            ("AWS_S3_BUCKET_NAME".to_string(), "bucket".to_string()),
+            ("KEY_DENY_REGEX".to_string(), "tmp/(".to_string()),
+        ]);
+        let err = load_config_from(&ConfigSource {
+            env,
+            file: HashMap::new(),
+        })
+        .unwrap_err();
+        assert!(err.to_string().contains("KEY_DENY_REGEX"), "{err}");
+    }
+
+    #[test]
+    fn validate_reports_every_broken_invariant() {
+        assert!(test_config(&[]).validate().is_ok());
+        let config = test_config(&[("PORT", "0"), ("AWS_S3_ENDPOINT_URL", "s3.local")]);
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("PORT must not be 0"), "{err}");
+        assert!(err.contains("AWS_S3_ENDPOINT_URL \"s3.local\""), "{err}");
+    }
+
+    #[actix_web::test]
+    async fn denied_keys_are_hidden_from_listings() {
+        let s3 = MockS3::with_keys(&["clip.mp4", "tmp/upload.mp4", "shows/tmp.mp4"]);
+        let state = test_state(&s3, &[("KEY_DENY_REGEX", "^tmp/")]);
+        let listing = get_json(&state, "/videos?recursive=true").await;
+        assert_eq!(keys(&listing, "videos"), ["clip.mp4", "shows/tmp.mp4"]);
+    }
+
+    #[test]
+    fn stable_hash_matches_fnv1a_reference_values() {
+        assert_eq!(stable_hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(stable_hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(object_id("a", None), "key-af63dc4c8601ec8c");
+        assert_eq!(object_id("a", Some("\"abc\"")), "abc");
+    }
+
+    #[test]
+    fn custom_delimiter_splits_names_folders_and_breadcrumbs() {
+        assert_eq!(basename("shows:s01:e01.mp4", ":"), "e01.mp4");
+        assert_eq!(folder_name("shows:s01:", ":"), "s01");
+        assert_eq!(folder_name("a/b/", "/"), "b");
+
+        let crumbs = breadcrumbs("shows:s01:", ":");
+        let crumbs: Vec<_> = crumbs
+            .iter()
+            .map(|crumb| (crumb.name.as_str(), crumb.prefix.as_str()))
+            .collect();
+        assert_eq!(crumbs, [("shows", "shows:"), ("s01", "shows:s01:")]);
+    }
+
+    #[actix_web::test]
+    async fn custom_delimiter_drives_folder_listings() {
+        let s3 = MockS3::with_keys(&["intro.mp4", "shows:s01:e01.mp4", "shows:s01:e02.mp4"]);
+        let state = test_state(&s3, &[("DELIMITER", ":")]);
+
+        let root = get_json(&state, "/videos").await;
+        assert_eq!(keys(&root, "videos"), ["intro.mp4"]);
+        assert_eq!(keys(&root, "folders"), ["shows:"]);
+        assert_eq!(root["folders"][0]["name"], "shows");
+
+        let season = get_json(&state, "/videos?prefix=shows:s01").await;
+        assert_eq!(season["prefix"], "shows:s01:");
+        assert_eq!(keys(&season, "videos"), ["shows:s01:e01.mp4", "shows:s01:e02.mp4"]);
+        assert_eq!(season["videos"][0]["title"], "e01");
+        assert_eq!(season["breadcrumbs"][1]["prefix"], "shows:s01:");
+    }
+
+    #[test]
+    fn validate_key_rejects_traversal_at_either_separator() {
+        assert!(validate_key("shows:s01:e01.mp4", ":").is_ok());
+        assert!(validate_key("shows:..:secret.mp4", ":").is_err());
+        assert!(validate_key("shows/../secret.mp4", ":").is_err());
+        assert!(validate_key(":shows", ":").is_err());
+        assert!(validate_key("/shows", ":").is_err());
+    }
+
+    #[actix_web::test]
+    async fn longest_matching_presign_override_wins() {
+        let state = offline_state(&[
+            ("PRESIGN_EXPIRY_SECONDS", "3600"),
+            ("PRESIGN_EXPIRY_OVERRIDES", "public/:86400, public/private/:60,pub:600"),
+        ]);
+        assert_eq!(state.presign_expiry_for("public/private/clip.mp4"), 60);
+        assert_eq!(state.presign_expiry_for("public/clip.mp4"), 86400);
+        assert_eq!(state.presign_expiry_for("pub.mp4"), 600);
+        assert_eq!(state.presign_expiry_for("private/clip.mp4"), 3600);
+
+        let uri = "/videos/stream/public/private/clip.mp4";
+        let response = get(&state, TestRequest::get().uri(uri)).await;
+        assert!(header_value(&response, header::LOCATION).contains("X-Amz-Expires=60&"));
+    }
+
+    #[test]
+    fn presign_overrides_need_valid_seconds() {
+        assert!(parse_presign_expiry_overrides_env(None).unwrap().is_empty());
+        assert!(parse_presign_expiry_overrides_env(Some("public/".to_string())).is_err());
+        assert!(parse_presign_expiry_overrides_env(Some("public/:0".to_string())).is_err());
+        assert!(parse_presign_expiry_overrides_env(Some("public/:soon".to_string())).is_err());
+        assert_eq!(
+            parse_presign_expiry_overrides_env(Some("a:1:60".to_string())).unwrap(),
+            [("a:1".to_string(), 60)]
+        );
+    }
+
+    #[actix_web::test]
+    async fn disabled_folders_list_every_key_flat() {
+        let s3 = MockS3::with_keys(&NESTED_KEYS);
+        let state = test_state(&s3, &[("FOLDERS_ENABLED", "false")]);
+        assert!(state.recursive(false));
+
+        let first = get_json(&state, "/videos?pageSize=3").await;
+        assert_eq!(keys(&first, "videos"), ["a.mp4", "shows/e00.mp4", "shows/s01/e01.mp4"]);
+        assert!(keys(&first, "folders").is_empty());
+        assert_eq!(first["pagination"]["totalVideos"], 4);
+        assert_eq!(first["pagination"]["hasNextPage"], true);
+
+        let second = get_json(&state, "/videos?pageSize=3&page=2").await;
+        assert_eq!(keys(&second, "videos"), ["shows/s01/e02.mp4"]);
+        assert!(s3.requests().iter().all(|request| !request.contains("delimiter=")));
+    }
+
+    #[test]
+    fn display_title_cleans_up_the_file_name() {
+        assert_eq!(display_title("2023/raw_dump_final_v2.mp4", "/", None), "raw dump final v2");
+        assert_eq!(display_title("a/My-Holiday  Clip.MOV", "/", None), "My Holiday Clip");
+        assert_eq!(display_title("shows:e01.mp4", ":", None), "e01");
+        assert_eq!(display_title(".mp4", "/", None), ".mp4");
+        assert_eq!(display_title("___.mp4", "/", None), "___.mp4");
+    }
+
+    #[test]
+    fn display_title_applies_the_strip_regex_before_splitting() {
+        let strip = Regex::new(r"(?i)_?(final|v\d+|1080p)").unwrap();
+        assert_eq!(display_title("2023/raw_dump_final_v2.mp4", "/", Some(&strip)), "raw dump");
+        assert_eq!(display_title("trip-1080p.mp4", "/", Some(&strip)), "trip");
+        assert_eq!(display_title("final.mp4", "/", Some(&strip)), "final.mp4");
+
+        let state = offline_state(&[("TITLE_STRIP_REGEX", "^\\d{4}-\\d{2}-\\d{2}_")]);
+        let title_strip = state.title_strip.as_ref();
+        assert_eq!(display_title("2024-05-01_beach_day.mp4", "/", title_strip), "beach day");
+    }
+
+    #[actix_web::test]
+    async fn hide_dotfiles_filters_dot_names_and_dot_folders() {
+        let state = offline_state(&[("HIDE_DOTFILES", "true")]);
+        assert!(state.is_hidden(".secret.mp4"));
+        assert!(state.is_hidden("shows/.trash/clip.mp4"));
+        assert!(state.is_hidden(".trash/"));
+        assert!(!state.is_hidden("shows/clip.v2.mp4"));
+        assert!(!offline_state(&[]).is_hidden(".secret.mp4"));
+
+        let s3 = MockS3::with_keys(&[
+            ".secret.mp4",
+            ".trash/old.mp4",
+            "clip.mp4",
+            "shows/.hidden.mp4",
+            "shows/e01.mp4",
+        ]);
+        let hidden = test_state(&s3, &[("HIDE_DOTFILES", "true")]);
+        let listing = get_json(&hidden, "/videos").await;
+        assert_eq!(keys(&listing, "videos"), ["clip.mp4"]);
+        assert_eq!(keys(&listing, "folders"), ["shows/"]);
+        let listing = get_json(&hidden, "/videos?recursive=true").await;
+        assert_eq!(keys(&listing, "videos"), ["clip.mp4", "shows/e01.mp4"]);
+
+        let shown = test_state(&s3, &[]);
+        let listing = get_json(&shown, "/videos").await;
+        assert_eq!(keys(&listing, "videos"), [".secret.mp4", "clip.mp4"]);
+        assert_eq!(keys(&listing, "folders"), [".trash/", "shows/"]);
+    }
 }
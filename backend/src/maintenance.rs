@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::StatusCode,
+    middleware::Next,
+    web::Data,
+    ResponseError,
+};
+
+use crate::error::ApiError;
+
+/// `Retry-After` sent with maintenance responses.
+const MAINTENANCE_RETRY_AFTER_SECONDS: u64 = 60;
+
+/// Liveness keeps answering during maintenance so the orchestrator doesn't
+/// restart the process; readiness fails with everything else, which is what
+/// takes the instance out of rotation.
+const EXEMPT_ROUTES: [&str; 1] = ["/health"];
+
+/// `MAINTENANCE_MODE`, or the presence of `MAINTENANCE_FILE`. The file is
+/// checked on every request, so creating or deleting it toggles maintenance
+/// without a restart.
+pub struct Maintenance {
+    pub enabled: bool,
+    pub file: Option<PathBuf>,
+}
+
+impl Maintenance {
+    fn is_active(&self) -> bool {
+        self.enabled || self.file.as_ref().is_some_and(|file| file.exists())
+    }
+}
+
+/// Answers every `/api` route but liveness with 503 while in maintenance.
+pub async fn check_maintenance(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let active = req
+        .app_data::<Data<Maintenance>>()
+        .is_some_and(|maintenance| maintenance.is_active());
+    if !active || EXEMPT_ROUTES.contains(&req.match_info().unprocessed()) {
+        return next.call(req).await.map(ServiceResponse::map_into_left_body);
+    }
+
+    let error = ApiError::new(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "maintenance",
+        "The service is down for maintenance, try again later",
+    )
+    .with_retry_after(MAINTENANCE_RETRY_AFTER_SECONDS);
+    Ok(req.into_response(error.error_response()).map_into_right_body())
+}
@@ -0,0 +1,110 @@
+use std::time::Instant;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    get,
+    http::header,
+    middleware::Next,
+    web::Data,
+    HttpResponse, Responder,
+};
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+
+/// Prometheus collectors shared by every worker.
+pub struct Metrics {
+    registry: Registry,
+    requests_total: IntCounterVec,
+    request_duration_seconds: HistogramVec,
+    s3_failures_total: IntCounterVec,
+    last_list_video_count: IntGauge,
+}
+
+impl Metrics {
+    pub fn new() -> prometheus::Result<Self> {
+        let registry = Registry::new_custom(Some("s3_streamer".to_string()), None)?;
+
+        let requests_total = IntCounterVec::new(
+            Opts::new("requests_total", "Total API requests by route and status"),
+            &["route", "status"],
+        )?;
+        let request_duration_seconds = HistogramVec::new(
+            HistogramOpts::new(
+                "request_duration_seconds",
+                "API handler latency by route in seconds",
+            ),
+            &["route"],
+        )?;
+        let s3_failures_total = IntCounterVec::new(
+            Opts::new("s3_failures_total", "Failed S3 calls by operation"),
+            &["operation"],
+        )?;
+        let last_list_video_count = IntGauge::new(
+            "last_list_video_count",
+            "Number of videos matched by the most recent list call",
+        )?;
+
+        registry.register(Box::new(requests_total.clone()))?;
+        registry.register(Box::new(request_duration_seconds.clone()))?;
+        registry.register(Box::new(s3_failures_total.clone()))?;
+        registry.register(Box::new(last_list_video_count.clone()))?;
+
+        Ok(Self {
+            registry,
+            requests_total,
+            request_duration_seconds,
+            s3_failures_total,
+            last_list_video_count,
+        })
+    }
+
+    pub fn record_s3_failure(&self, operation: &str) {
+        self.s3_failures_total.with_label_values(&[operation]).inc();
+    }
+
+    pub fn set_last_list_video_count(&self, count: usize) {
+        self.last_list_video_count
+            .set(i64::try_from(count).unwrap_or(i64::MAX));
+    }
+}
+
+/// Records request counts and latency labelled by the matched route pattern,
+/// so `/videos/stream/{key:.*}` is one series rather than one per key.
+pub async fn track_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let metrics = req.app_data::<Data<Metrics>>().cloned();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let started = Instant::now();
+
+    let response = next.call(req).await?;
+
+    if let Some(metrics) = metrics {
+        let status = response.status().as_u16().to_string();
+        metrics
+            .requests_total
+            .with_label_values(&[route.as_str(), status.as_str()])
+            .inc();
+        metrics
+            .request_duration_seconds
+            .with_label_values(&[route.as_str()])
+            .observe(started.elapsed().as_secs_f64());
+    }
+
+    Ok(response)
+}
+
+#[get("/metrics")]
+pub async fn metrics_endpoint(metrics: Data<Metrics>) -> impl Responder {
+    match TextEncoder::new().encode_to_string(&metrics.registry.gather()) {
+        Ok(body) => HttpResponse::Ok()
+            .insert_header((header::CONTENT_TYPE, prometheus::TEXT_FORMAT))
+            .body(body),
+        Err(err) => HttpResponse::InternalServerError().body(err.to_string()),
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use aws_config::BehaviorVersion;
+use aws_credential_types::Credentials;
+use aws_sdk_s3::{
+    config::{
+        http::{HttpRequest, HttpResponse},
+        retry::RetryConfig,
+        Region,
+    },
+    primitives::SdkBody,
+    Client,
+};
+use aws_smithy_runtime_api::client::{
+    http::{
+        HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings,
+        SharedHttpConnector,
+    },
+    runtime_components::RuntimeComponents,
+};
+
+type Responder = dyn Fn(&HttpRequest) -> HttpResponse + Send + Sync;
+
+/// Size of every object in a `with_keys` bucket.
+pub const OBJECT_SIZE: u64 = 1024;
+
+/// An S3 endpoint answered by a closure, for tests that exercise the calls
+/// the handlers make. Every request is recorded.
+#[derive(Clone)]
+pub struct MockS3 {
+    respond: Arc<Responder>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl fmt::Debug for MockS3 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockS3").finish_non_exhaustive()
+    }
+}
+
+impl MockS3 {
+    /// Answers each request with the status and body `respond` returns for
+    /// its method and URI.
+    pub fn new(respond: impl Fn(&str, &str) -> (u16, String) + Send + Sync + 'static) -> Self {
+        Self::from_fn(move |request| {
+            let (status, body) = respond(request.method(), request.uri());
+            response(status, body)
+        })
+    }
+
+    pub fn from_fn(respond: impl Fn(&HttpRequest) -> HttpResponse + Send + Sync + 'static) -> Self {
+        Self {
+            respond: Arc::new(respond),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// A bucket holding `keys`, each `OBJECT_SIZE` bytes. Single-page
+    /// `ListObjectsV2` calls follow S3's prefix and delimiter semantics, and
+    /// `GetObject` honours `bytes=first-[last]` ranges.
+    pub fn with_keys(keys: &[&str]) -> Self {
+        let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+        Self::from_fn(move |request| {
+            let uri = request.uri();
+            let (path, query) = uri.split_once('?').unwrap_or((uri, ""));
+            let key = path
+                .splitn(5, '/')
+                .nth(4)
+                .filter(|key| !key.is_empty())
+                .map(|key| urlencoding::decode(key).unwrap_or_default().into_owned());
+            match key {
+                None => response(200, bucket_listing(&keys, query)),
+                Some(key) if !keys.contains(&key) => match request.method() {
+                    "HEAD" => response(404, String::new()),
+                    _ => response(404, error_body("NoSuchKey")),
+                },
+                Some(_) => object_response(request.method(), request.headers().get("range")),
+            }
+        })
+    }
+
+    /// A path-style client for `http://s3.test` that sends everything here.
+    pub fn client(&self) -> Client {
+        let config = aws_sdk_s3::Config::builder()
+            .behavior_version(BehaviorVersion::latest())
+            .region(Region::new("us-east-1"))
+            .credentials_provider(Credentials::new("test", "test", None, None, "test"))
+            .endpoint_url("http://s3.test")
+            .force_path_style(true)
+            .retry_config(RetryConfig::disabled())
+            .http_client(self.clone())
+            .build();
+        Client::from_conf(config)
+    }
+
+    /// `METHOD uri` of every request so far, oldest first.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+impl HttpConnector for MockS3 {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let (method, uri) = (request.method().to_string(), request.uri().to_string());
+        self.requests.lock().unwrap().push(format!("{method} {uri}"));
+        HttpConnectorFuture::ready(Ok((self.respond)(&request)))
+    }
+}
+
+impl HttpClient for MockS3 {
+    fn http_connector(
+        &self,
+        _settings: &HttpConnectorSettings,
+        _components: &RuntimeComponents,
+    ) -> SharedHttpConnector {
+        SharedHttpConnector::new(self.clone())
+    }
+}
+
+pub fn response(status: u16, body: impl Into<String>) -> HttpResponse {
+    let status = status.try_into().expect("valid status code");
+    HttpResponse::new(status, SdkBody::from(body.into()))
+}
+
+pub fn error_body(code: &str) -> String {
+    format!("<Error><Code>{code}</Code><Message>{code}</Message></Error>")
+}
+
+fn object_response(method: &str, range: Option<&str>) -> HttpResponse {
+    let range = range.and_then(|range| {
+        let (first, last) = range.strip_prefix("bytes=")?.split_once('-')?;
+        let first: u64 = first.parse().ok()?;
+        let last = last.parse().unwrap_or(OBJECT_SIZE - 1).min(OBJECT_SIZE - 1);
+        Some((first, last))
+    });
+    let (status, length, content_range) = match range {
+        Some((first, _)) if first >= OBJECT_SIZE => return response(416, error_body("InvalidRange")),
+        Some((first, last)) => (206, last - first + 1, Some(format!("bytes {first}-{last}/{OBJECT_SIZE}"))),
+        None => (200, OBJECT_SIZE, None),
+    };
+    let body = if method == "HEAD" { String::new() } else { "x".repeat(length as usize) };
+    let mut response = response(status, body);
+    response.headers_mut().insert("content-length", length.to_string());
+    if let Some(content_range) = content_range {
+        response.headers_mut().insert("content-range", content_range);
+    }
+    response
+}
+
+/// A `ListObjectsV2` result page holding `keys`, each 1 KiB, continuing at
+/// `next_token` when given.
+pub fn list_page(keys: &[&str], next_token: Option<&str>) -> String {
+    let objects: Vec<(&str, u64)> = keys.iter().map(|key| (*key, 1024)).collect();
+    sized_list_page(&objects, next_token)
+}
+
+/// A `ListObjectsV2` result page holding `(key, size)` objects.
+pub fn sized_list_page(objects: &[(&str, u64)], next_token: Option<&str>) -> String {
+    let contents: String = objects
+        .iter()
+        .map(|(key, size)| {
+            format!(
+                "<Contents><Key>{key}</Key><LastModified>2024-01-01T00:00:00.000Z</LastModified>\
+                 <ETag>\"etag\"</ETag><Size>{size}</Size></Contents>"
+            )
+        })
+        .collect();
+    let continuation = match next_token {
+        Some(token) => format!(
+            "<IsTruncated>true</IsTruncated><NextContinuationToken>{token}</NextContinuationToken>"
+        ),
+        None => "<IsTruncated>false</IsTruncated>".to_string(),
+    };
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+         <ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+         <Name>bucket</Name><KeyCount>{}</KeyCount>{continuation}{contents}</ListBucketResult>",
+        objects.len()
+    )
+}
+
+/// The listing S3 would return for `keys` given a `ListObjectsV2` query
+/// string: keys under `prefix`, with those past the next `delimiter` rolled
+/// up into common prefixes.
+fn bucket_listing(keys: &[String], query: &str) -> String {
+    let param = |name: &str| {
+        query.split('&').find_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            (key == name).then(|| urlencoding::decode(value).unwrap_or_default().into_owned())
+        })
+    };
+    let prefix = param("prefix").unwrap_or_default();
+    let delimiter = param("delimiter").filter(|delimiter| !delimiter.is_empty());
+
+    let mut objects = Vec::new();
+    let mut common_prefixes: Vec<String> = Vec::new();
+    for key in keys.iter().filter(|key| key.starts_with(&prefix)) {
+        let rest = &key[prefix.len()..];
+        match delimiter.as_deref().and_then(|delimiter| {
+            rest.find(delimiter).map(|at| &key[..prefix.len() + at + delimiter.len()])
+        }) {
+            Some(common) if !common_prefixes.iter().any(|seen| seen == common) => {
+                common_prefixes.push(common.to_string())
+            }
+            Some(_) => {}
+            None => objects.push(key.as_str()),
+        }
+    }
+
+    let folders: String = common_prefixes
+        .iter()
+        .map(|prefix| format!("<CommonPrefixes><Prefix>{prefix}</Prefix></CommonPrefixes>"))
+        .collect();
+    list_page(&objects, None).replace("</ListBucketResult>", &format!("{folders}</ListBucketResult>"))
+}
@@ -0,0 +1,146 @@
+use std::{
+    io::{self, Read},
+    process::{Command, Stdio},
+    time::Duration,
+};
+
+use actix_web::web;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    thumbnail::{presigned_tool_url, wait_with_deadline, TOOL_RW_TIMEOUT_MICROS},
+    AppState,
+};
+
+/// Results are keyed by ETag, so they never go stale; the TTL and cap only
+/// bound how much a long-running server keeps in memory.
+pub const PROBE_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+pub const PROBE_CACHE_MAX_ENTRIES: usize = 4096;
+
+/// Hard limit on one ffprobe run, for when it keeps making slow progress.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Duration and resolution of a video, each omitted when ffprobe can't tell.
+#[derive(Clone, Default, Serialize)]
+pub struct MediaInfo {
+    #[serde(rename = "durationSeconds", skip_serializing_if = "Option::is_none")]
+    pub duration_seconds: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ProbeOutput {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    width: Option<u32>,
+    height: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+fn parse_probe_output(stdout: &[u8]) -> MediaInfo {
+    let Ok(output) = serde_json::from_slice::<ProbeOutput>(stdout) else {
+        return MediaInfo::default();
+    };
+    let stream = output.streams.into_iter().next();
+    MediaInfo {
+        duration_seconds: output
+            .format
+            .and_then(|format| format.duration)
+            .and_then(|duration| duration.parse::<f64>().ok())
+            .filter(|duration| duration.is_finite() && *duration > 0.0),
+        width: stream.as_ref().and_then(|stream| stream.width).filter(|w| *w > 0),
+        height: stream.and_then(|stream| stream.height).filter(|h| *h > 0),
+    }
+}
+
+/// ffprobe reads only the container header (the moov atom for MP4), so this
+/// costs a few ranged requests rather than a download. A run that outlasts
+/// `PROBE_TIMEOUT` is killed and reported as `TimedOut`. Its JSON is a few
+/// hundred bytes, well within a pipe buffer, so it is read after exit.
+fn run_ffprobe(ffprobe: &str, url: &str) -> io::Result<MediaInfo> {
+    let mut child = Command::new(ffprobe)
+        .args(["-v", "error", "-rw_timeout", TOOL_RW_TIMEOUT_MICROS])
+        .args(["-select_streams", "v:0"])
+        .args(["-show_entries", "stream=width,height:format=duration", "-of", "json"])
+        .arg(url)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+    let Some(status) = wait_with_deadline(&mut child, PROBE_TIMEOUT)? else {
+        return Err(io::Error::new(io::ErrorKind::TimedOut, "ffprobe timed out"));
+    };
+    if !status.success() {
+        return Ok(MediaInfo::default());
+    }
+    let mut stdout = Vec::new();
+    if let Some(mut pipe) = child.stdout.take() {
+        pipe.read_to_end(&mut stdout)?;
+    }
+    Ok(parse_probe_output(&stdout))
+}
+
+/// Probes video objects for duration and resolution. Anything that isn't a
+/// video, or that ffprobe can't read, yields empty info instead of an error
+/// so metadata requests never fail because of it.
+pub async fn probe_media(
+    state: &AppState,
+    key: &str,
+    e_tag: Option<&str>,
+    content_type: Option<&str>,
+) -> MediaInfo {
+    let is_video =
+        content_type.is_some_and(|ct| ct.starts_with("video/")) || state.is_video_key(key);
+    if !is_video {
+        return MediaInfo::default();
+    }
+    // ETags are only meaningful per object, so the key is part of the cache key.
+    let cache_key = e_tag
+        .filter(|tag| !tag.is_empty())
+        .map(|tag| format!("{key}\n{tag}"));
+    if let Some(info) = cache_key.as_deref().and_then(|k| state.probe_cache.get(k)) {
+        return info;
+    }
+
+    let url = match presigned_tool_url(state, key).await {
+        Ok(url) => url,
+        Err(err) => {
+            tracing::warn!("Skipping probe of {key:?}: {err}");
+            return MediaInfo::default();
+        }
+    };
+    let ffprobe = state.ffprobe_path.clone();
+    let info = match web::block(move || run_ffprobe(&ffprobe, &url)).await {
+        Ok(Ok(info)) => info,
+        Ok(Err(err)) if err.kind() == io::ErrorKind::NotFound => {
+            tracing::debug!("ffprobe is not installed; metadata will omit duration and resolution");
+            return MediaInfo::default();
+        }
+        Ok(Err(err)) => {
+            tracing::warn!("Failed to run ffprobe on {key:?}: {err}");
+            return MediaInfo::default();
+        }
+        Err(err) => {
+            tracing::warn!("Probe of {key:?} was cancelled: {err}");
+            return MediaInfo::default();
+        }
+    };
+
+    // Unprobable files are cached too, so they aren't retried on every request.
+    if let Some(cache_key) = cache_key {
+        state.probe_cache.insert(cache_key, info.clone());
+    }
+    info
+}
@@ -0,0 +1,139 @@
+use std::{collections::HashMap, net::IpAddr, sync::Mutex, time::Instant};
+
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, StatusCode},
+    middleware::Next,
+    web::Data,
+    ResponseError,
+};
+
+use crate::error::ApiError;
+
+/// Clients tracked at once, so a flood of distinct addresses can't grow the
+/// map without bound.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// What a sweep shrinks the map to. Freeing a tenth of the capacity at once
+/// keeps the sweep's O(n) cost to one in every thousand new clients.
+const SWEEP_TARGET: usize = MAX_TRACKED_CLIENTS / 10 * 9;
+
+/// Probes are never limited: a kubelet or load balancer sharing an address
+/// with real traffic must not get 429s and take the instance down. Paths are
+/// relative to the `/api` scope, as in `auth`.
+const EXEMPT_ROUTES: [&str; 2] = ["/health", "/ready"];
+
+/// `RATE_LIMIT_RPS` requests per second on average, in bursts of up to
+/// `RATE_LIMIT_BURST`.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimit {
+    pub per_second: f64,
+    pub burst: f64,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn tokens_at(&self, limit: RateLimit, now: Instant) -> f64 {
+        let refill = now.duration_since(self.refilled).as_secs_f64() * limit.per_second;
+        (self.tokens + refill).min(limit.burst)
+    }
+}
+
+/// Token buckets keyed by client IP. A single instance is shared by every
+/// worker through `Data`, so the limit holds per process; each replica of a
+/// scaled-out deployment still allows the full rate.
+pub struct RateLimiter {
+    limit: Option<RateLimit>,
+    trust_proxy: bool,
+    buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: Option<RateLimit>, trust_proxy: bool) -> Self {
+        Self {
+            limit,
+            trust_proxy,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// With `TRUST_PROXY`, the last `X-Forwarded-For` hop, which is the one
+    /// the proxy in front of us appended; otherwise the socket peer.
+    fn client_ip(&self, req: &ServiceRequest) -> Option<IpAddr> {
+        let forwarded = self
+            .trust_proxy
+            .then(|| req.headers().get(header::X_FORWARDED_FOR))
+            .flatten()
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|hop| hop.trim().parse().ok());
+        forwarded.or_else(|| req.peer_addr().map(|addr| addr.ip()))
+    }
+
+    /// Takes a token for `ip`, or returns how many seconds until one frees up.
+    fn acquire(&self, limit: RateLimit, ip: IpAddr) -> Result<(), u64> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(&ip) {
+            sweep(&mut buckets, limit, now);
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: limit.burst,
+            refilled: now,
+        });
+        bucket.tokens = bucket.tokens_at(limit, now);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / limit.per_second).ceil() as u64)
+        }
+    }
+}
+
+/// Shrinks `buckets` to `SWEEP_TARGET`: first drops full buckets, which are
+/// indistinguishable from fresh ones, then the least recently seen clients.
+fn sweep(buckets: &mut HashMap<IpAddr, Bucket>, limit: RateLimit, now: Instant) {
+    buckets.retain(|_, bucket| bucket.tokens_at(limit, now) < limit.burst);
+    if buckets.len() <= SWEEP_TARGET {
+        return;
+    }
+    let mut refilled: Vec<Instant> = buckets.values().map(|bucket| bucket.refilled).collect();
+    let excess = buckets.len() - SWEEP_TARGET;
+    let (_, cutoff, _) = refilled.select_nth_unstable(excess - 1);
+    let cutoff = *cutoff;
+    buckets.retain(|_, bucket| bucket.refilled > cutoff);
+}
+
+/// Answers 429 with `Retry-After` once a client has used up its bucket.
+/// Requests whose address can't be determined, and probes, are let through.
+pub async fn limit_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let exempt = EXEMPT_ROUTES.contains(&req.match_info().unprocessed());
+    let retry_after = req.app_data::<Data<RateLimiter>>().and_then(|limiter| {
+        let limit = limiter.limit.filter(|_| !exempt)?;
+        let ip = limiter.client_ip(&req)?;
+        limiter.acquire(limit, ip).err()
+    });
+
+    match retry_after {
+        None => next.call(req).await.map(ServiceResponse::map_into_left_body),
+        Some(seconds) => {
+            let error = ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                "Too many requests, try again later",
+            )
+            .with_retry_after(seconds);
+            Ok(req.into_response(error.error_response()).map_into_right_body())
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use actix_web::{
+    http::StatusCode,
+    post,
+    web::{Data, Path, Query},
+    HttpRequest, HttpResponse,
+};
+use aws_sdk_s3::{
+    error::ProvideErrorMetadata,
+    types::{GlacierJobParameters, RestoreRequest, Tier},
+};
+use serde::Deserialize;
+
+use crate::{
+    auth, decode_key, error::ApiError, head_object, in_archive_tier, AppState, RestoreStatus,
+};
+
+/// How long a restored copy stays readable when the request names no `days`.
+const DEFAULT_RESTORE_DAYS: i32 = 7;
+
+#[derive(Deserialize)]
+pub struct RestoreQuery {
+    days: Option<i32>,
+    /// `standard` (the default), `bulk` or `expedited`.
+    tier: Option<String>,
+}
+
+fn parse_tier(tier: Option<&str>) -> Result<Tier, ApiError> {
+    match tier.map(str::to_ascii_lowercase).as_deref() {
+        None | Some("standard") => Ok(Tier::Standard),
+        Some("bulk") => Ok(Tier::Bulk),
+        Some("expedited") => Ok(Tier::Expedited),
+        Some(_) => Err(ApiError::bad_request(
+            "tier must be standard, bulk or expedited",
+        )),
+    }
+}
+
+fn restore_in_progress(key: &str) -> ApiError {
+    ApiError::new(
+        StatusCode::CONFLICT,
+        "restore_in_progress",
+        format!("A restore of {key} is already in progress"),
+    )
+}
+
+/// Asks S3 to restore an archived object. Restores take minutes to hours, so
+/// this answers 202 at once; clients poll `/videos/meta` for `restoreStatus`.
+#[post("/videos/restore/{key:.*}")]
+pub async fn restore_video(
+    req: HttpRequest,
+    state: Data<AppState>,
+    path: Path<String>,
+    query: Query<RestoreQuery>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_admin(&req)?;
+    let key = decode_key(&path.into_inner(), &state.delimiter)?;
+    let tier = parse_tier(query.tier.as_deref())?;
+    let days = query.days.unwrap_or(DEFAULT_RESTORE_DAYS);
+    if days < 1 {
+        return Err(ApiError::bad_request("days must be at least 1"));
+    }
+
+    let head = head_object(&state, &key).await?;
+    if !in_archive_tier(&head) {
+        return Err(ApiError::bad_request(format!("{key} is not archived")));
+    }
+    if RestoreStatus::from_header(head.restore()) == RestoreStatus::Ongoing {
+        return Err(restore_in_progress(&key));
+    }
+
+    // Intelligent-Tiering restores move the object back to an access tier
+    // rather than making a temporary copy, and S3 rejects them with `Days`.
+    let days = head.archive_status().is_none().then_some(days);
+    let job = GlacierJobParameters::builder()
+        .tier(tier)
+        .build()
+        .map_err(|err| ApiError::internal(format!("Invalid restore request: {err}")))?;
+    let restore_request = RestoreRequest::builder()
+        .set_days(days)
+        .glacier_job_parameters(job)
+        .build();
+
+    let s3_key = state.s3_key(&key);
+    let restore = state.retry.run("restore_object", || {
+        state
+            .s3
+            .restore_object()
+            .bucket(&state.bucket)
+            .key(&s3_key)
+            .restore_request(restore_request.clone())
+            .set_request_payer(state.request_payer.clone())
+            .send()
+    });
+    match state
+        .time_s3("restore_object", &state.bucket, &s3_key, restore)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Accepted().finish()),
+        Err(err) if err.code() == Some("RestoreAlreadyInProgress") => {
+            Err(restore_in_progress(&key))
+        }
+        Err(err) => {
+            state.metrics.record_s3_failure("restore_object");
+            Err(ApiError::from_s3("Failed to restore object", err))
+        }
+    }
+}
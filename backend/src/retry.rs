@@ -0,0 +1,143 @@
+use std::{future::Future, time::Duration};
+
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+
+/// Error codes S3 and S3-compatible gateways use for throttling and
+/// transient server-side failures.
+const RETRYABLE_CODES: [&str; 7] = [
+    "Throttling",
+    "ThrottlingException",
+    "SlowDown",
+    "RequestTimeout",
+    "RequestTimeoutException",
+    "InternalError",
+    "ServiceUnavailable",
+];
+
+/// Retries S3 calls that failed for transient reasons using exponential
+/// backoff with full jitter. The SDK's own retries are disabled in
+/// `build_s3_client` so this is the only policy in effect.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+
+    pub async fn run<T, E, F, Fut>(&self, operation: &str, mut call: F) -> Result<T, SdkError<E>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E>>>,
+        E: ProvideErrorMetadata,
+    {
+        let mut attempt = 0;
+        loop {
+            match call().await {
+                Err(err) if attempt < self.max_retries && is_retryable(&err) => {
+                    let delay = self.backoff(attempt);
+                    attempt += 1;
+                    tracing::debug!(
+                        operation,
+                        attempt,
+                        max_retries = self.max_retries,
+                        delay_ms = delay.as_millis() as u64,
+                        "Retrying S3 call after transient failure: {err}"
+                    );
+                    actix_web::rt::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Picks a random delay between zero and the exponential ceiling for `attempt`.
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let ceiling_ms = ceiling.as_millis() as u64;
+        Duration::from_millis(fastrand::u64(..=ceiling_ms))
+    }
+}
+
+/// Only throttling, timeouts, connection failures and 5xx responses are worth
+/// retrying; missing keys and auth errors will fail the same way again.
+fn is_retryable<E: ProvideErrorMetadata>(err: &SdkError<E>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::ResponseError(_) => true,
+        SdkError::DispatchFailure(failure) => failure.is_timeout() || failure.is_io(),
+        SdkError::ServiceError(context) => {
+            let status = context.raw().status().as_u16();
+            status >= 500
+                || status == 429
+                || context
+                    .err()
+                    .code()
+                    .is_some_and(|code| RETRYABLE_CODES.contains(&code))
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+    use crate::mock_s3::{list_page, MockS3};
+
+    /// Fails the first `failures` calls with `status`, then lists one key.
+    fn flaky_s3(failures: usize, status: u16, code: &str) -> MockS3 {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let error = format!("<Error><Code>{code}</Code><Message>failed</Message></Error>");
+        MockS3::new(move |_, _| {
+            if calls.fetch_add(1, Ordering::SeqCst) < failures {
+                (status, error.clone())
+            } else {
+                (200, list_page(&["a.mp4"], None))
+            }
+        })
+    }
+
+    async fn list(policy: &RetryPolicy, s3: &MockS3) -> bool {
+        let client = s3.client();
+        policy
+            .run("list_objects_v2", || client.list_objects_v2().bucket("bucket").send())
+            .await
+            .is_ok()
+    }
+
+    #[actix_web::test]
+    async fn transient_failures_are_retried_until_success() {
+        let s3 = flaky_s3(2, 503, "SlowDown");
+        assert!(list(&RetryPolicy::new(2), &s3).await);
+        assert_eq!(s3.requests().len(), 3);
+    }
+
+    #[actix_web::test]
+    async fn retries_stop_at_the_limit() {
+        let s3 = flaky_s3(2, 500, "InternalError");
+        assert!(!list(&RetryPolicy::new(1), &s3).await);
+        assert_eq!(s3.requests().len(), 2);
+    }
+
+    #[actix_web::test]
+    async fn client_errors_are_not_retried() {
+        let s3 = flaky_s3(1, 403, "AccessDenied");
+        assert!(!list(&RetryPolicy::new(3), &s3).await);
+        assert_eq!(s3.requests().len(), 1);
+    }
+}
@@ -0,0 +1,80 @@
+use std::{
+    cell::Cell,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    web::Data,
+};
+
+use crate::AppState;
+
+const SERVER_TIMING: HeaderName = HeaderName::from_static("server-timing");
+
+/// S3 time spent on behalf of one request, split so presigning (local
+/// signing, no network) doesn't hide in the S3 total.
+#[derive(Default)]
+struct Timings {
+    s3: Cell<Option<Duration>>,
+    presign: Cell<Option<Duration>>,
+}
+
+tokio::task_local! {
+    static TIMINGS: Timings;
+}
+
+/// Adds one S3 call to the current request's totals. A no-op outside
+/// `add_server_timing`, e.g. in background tasks.
+pub fn record(operation: &str, elapsed: Duration) {
+    let _ = TIMINGS.try_with(|timings| {
+        let total = if operation.starts_with("presign_") {
+            &timings.presign
+        } else {
+            &timings.s3
+        };
+        total.set(Some(total.get().unwrap_or_default() + elapsed));
+    });
+}
+
+fn metric(name: &str, duration: Duration) -> String {
+    format!("{name};dur={:.1}", duration.as_secs_f64() * 1000.0)
+}
+
+/// With `SERVER_TIMING` on, reports summed S3 and presign time plus the
+/// handler's total in a `Server-Timing` header for browser devtools.
+/// Concurrent S3 calls are summed, so `s3` can exceed `total`.
+pub async fn add_server_timing(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let enabled = req
+        .app_data::<Data<AppState>>()
+        .is_some_and(|state| state.server_timing);
+    if !enabled {
+        return next.call(req).await;
+    }
+
+    let started = Instant::now();
+    let (response, s3, presign) = TIMINGS
+        .scope(Timings::default(), async move {
+            let response = next.call(req).await;
+            let (s3, presign) = TIMINGS.with(|timings| (timings.s3.get(), timings.presign.get()));
+            (response, s3, presign)
+        })
+        .await;
+    let mut response = response?;
+
+    let metrics: Vec<String> = [("s3", s3), ("presign", presign)]
+        .into_iter()
+        .filter_map(|(name, duration)| Some(metric(name, duration?)))
+        .chain([metric("total", started.elapsed())])
+        .collect();
+    if let Ok(value) = HeaderValue::from_str(&metrics.join(", ")) {
+        response.headers_mut().insert(SERVER_TIMING, value);
+    }
+    Ok(response)
+}
@@ -0,0 +1,190 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use actix_web::{
+    cookie::{time, Cookie, CookieJar, Key, SameSite},
+    post,
+    web::{self, Data},
+    HttpRequest, HttpResponse,
+};
+use anyhow::Result;
+use argon2::{password_hash::PasswordHash, Argon2, PasswordVerifier};
+use serde::Deserialize;
+use subtle::ConstantTimeEq;
+
+use crate::error::ApiError;
+
+/// Cookie carrying the signed `username|expiry` session value.
+const SESSION_COOKIE: &str = "s3_streamer_session";
+
+/// `Key::derive_from` needs at least this much key material.
+const MIN_SECRET_BYTES: usize = 32;
+
+/// Credentials and signing key for browser logins.
+#[derive(Clone)]
+pub struct SessionConfig {
+    username: String,
+    /// Argon2 PHC string, e.g. `$argon2id$v=19$m=19456,t=2,p=1$...`.
+    password_hash: String,
+    key: Key,
+    ttl: Duration,
+    /// Marks the cookie `Secure`; only turn off for plain-HTTP local testing.
+    secure: bool,
+    path: String,
+}
+
+/// Keeps the hash and key out of logs.
+impl fmt::Debug for SessionConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionConfig")
+            .field("username", &self.username)
+            .field("ttl", &self.ttl)
+            .field("secure", &self.secure)
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SessionConfig {
+    /// Login is only enabled when both the username and hash are set, and
+    /// then requires a `SESSION_SECRET` long enough to derive a key from.
+    pub fn from_vars(
+        username: Option<String>,
+        password_hash: Option<String>,
+        secret: Option<String>,
+        ttl_seconds: u64,
+        secure: bool,
+        path: String,
+    ) -> Result<Option<Self>> {
+        let (username, password_hash) = match (
+            username.filter(|v| !v.is_empty()),
+            password_hash.filter(|v| !v.is_empty()),
+        ) {
+            (Some(username), Some(password_hash)) => (username, password_hash),
+            (None, None) => return Ok(None),
+            (Some(_), None) => {
+                anyhow::bail!("AUTH_USERNAME is set but AUTH_PASSWORD_HASH is missing")
+            }
+            (None, Some(_)) => {
+                anyhow::bail!("AUTH_PASSWORD_HASH is set but AUTH_USERNAME is missing")
+            }
+        };
+        if let Err(err) = PasswordHash::new(&password_hash) {
+            anyhow::bail!("AUTH_PASSWORD_HASH is not a valid argon2 hash: {err}");
+        }
+        let secret = secret.unwrap_or_default();
+        if secret.len() < MIN_SECRET_BYTES {
+            anyhow::bail!(
+                "SESSION_SECRET must be at least {MIN_SECRET_BYTES} bytes when login is enabled"
+            );
+        }
+
+        Ok(Some(Self {
+            username,
+            password_hash,
+            key: Key::derive_from(secret.as_bytes()),
+            ttl: Duration::from_secs(ttl_seconds),
+            secure,
+            path,
+        }))
+    }
+
+    fn cookie(&self, value: String, max_age: time::Duration) -> Cookie<'static> {
+        Cookie::build(SESSION_COOKIE, value)
+            .path(self.path.clone())
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(SameSite::Lax)
+            .max_age(max_age)
+            .finish()
+    }
+
+    /// Whether the request carries an unexpired session cookie signed with our key.
+    pub fn is_authenticated(&self, req: &HttpRequest) -> bool {
+        let Some(cookie) = req.cookie(SESSION_COOKIE) else {
+            return false;
+        };
+        let mut jar = CookieJar::new();
+        jar.add_original(cookie);
+        let Some(verified) = jar.signed(&self.key).get(SESSION_COOKIE) else {
+            return false;
+        };
+
+        // The cookie's Max-Age is only advisory, so the expiry is signed too.
+        let Some((username, expires_at)) = verified.value().rsplit_once('|') else {
+            return false;
+        };
+        username == self.username
+            && expires_at
+                .parse::<u64>()
+                .is_ok_and(|expires_at| unix_now() < expires_at)
+    }
+}
+
+/// The configured login, or `None` when browser sessions are disabled.
+pub struct SessionAuth(pub Option<SessionConfig>);
+
+#[derive(Deserialize)]
+pub struct LoginRequest {
+    username: String,
+    password: String,
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+/// Checks the credentials and sets a signed session cookie.
+#[post("/login")]
+pub async fn login(
+    auth: Data<SessionAuth>,
+    body: web::Json<LoginRequest>,
+) -> Result<HttpResponse, ApiError> {
+    let Some(config) = auth.0.as_ref() else {
+        return Err(ApiError::not_found("Login is not enabled"));
+    };
+    let LoginRequest { username, password } = body.into_inner();
+
+    // Argon2 is deliberately slow, so keep it off the async workers.
+    let password_hash = config.password_hash.clone();
+    let password_ok = web::block(move || {
+        PasswordHash::new(&password_hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    })
+    .await
+    .map_err(|err| ApiError::internal(format!("Failed to verify password: {err}")))?;
+    let username_ok: bool = username.as_bytes().ct_eq(config.username.as_bytes()).into();
+
+    if !(username_ok && password_ok) {
+        return Err(ApiError::unauthorized("Invalid username or password"));
+    }
+
+    let expires_at = unix_now().saturating_add(config.ttl.as_secs());
+    let max_age =
+        time::Duration::seconds(i64::try_from(config.ttl.as_secs()).unwrap_or(i64::MAX));
+    let mut jar = CookieJar::new();
+    jar.signed_mut(&config.key)
+        .add(config.cookie(format!("{}|{expires_at}", config.username), max_age));
+    let mut response = HttpResponse::NoContent();
+    if let Some(cookie) = jar.get(SESSION_COOKIE) {
+        response.cookie(cookie.clone());
+    }
+    Ok(response.finish())
+}
+
+/// Clears the session cookie; harmless when there is no session.
+#[post("/logout")]
+pub async fn logout(auth: Data<SessionAuth>) -> HttpResponse {
+    let mut response = HttpResponse::NoContent();
+    if let Some(config) = auth.0.as_ref() {
+        response.cookie(config.cookie(String::new(), time::Duration::ZERO));
+    }
+    response.finish()
+}
@@ -0,0 +1,300 @@
+use std::{
+    fmt::Write,
+    io,
+    path::Path as FsPath,
+    process::Command,
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    web::{self, Data, Path, Query},
+    HttpResponse,
+};
+use serde::Deserialize;
+
+use crate::{
+    decode_key,
+    error::ApiError,
+    head_object,
+    probe::{probe_media, MediaInfo},
+    thumbnail::{
+        cache_stem, jpeg_response, partial_path, presigned_tool_url, run_ffmpeg_job, tool_error,
+        TOOL_RW_TIMEOUT_MICROS,
+    },
+    AppState,
+};
+
+const DEFAULT_FRAMES: u32 = 20;
+const MAX_FRAMES: u32 = 100;
+const DEFAULT_TILE_WIDTH: u32 = 160;
+const MIN_TILE_WIDTH: u32 = 32;
+const MAX_TILE_WIDTH: u32 = 320;
+/// Tiles per sprite row; fewer frames make a single shorter row.
+const MAX_COLUMNS: u32 = 10;
+/// Limit on a whole sheet, on top of each ffmpeg run's own deadline, so a
+/// hundred slow frames can't hold a permit for an hour.
+const SPRITE_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Deserialize)]
+pub struct SpriteQuery {
+    /// `vtt` (the default) for the cue file, `jpg` for the sheet it points at.
+    format: Option<String>,
+    frames: Option<u32>,
+    width: Option<u32>,
+}
+
+/// Where each frame sits on the sheet and which stretch of video it covers.
+struct SpriteLayout {
+    frames: u32,
+    columns: u32,
+    rows: u32,
+    tile_width: u32,
+    tile_height: u32,
+    duration: f64,
+}
+
+impl SpriteLayout {
+    /// Tiles keep the video's aspect ratio (16:9 when ffprobe can't tell),
+    /// rounded to even sizes for the JPEG encoder. Needs a known duration.
+    fn new(frames: u32, tile_width: u32, media: &MediaInfo) -> Option<Self> {
+        let duration = media.duration_seconds?;
+        let aspect = match (media.width, media.height) {
+            (Some(width), Some(height)) => f64::from(height) / f64::from(width),
+            _ => 9.0 / 16.0,
+        };
+        let tile_height = ((f64::from(tile_width) * aspect).round() as u32).max(2) & !1;
+        let columns = frames.min(MAX_COLUMNS);
+        Some(Self {
+            frames,
+            columns,
+            rows: frames.div_ceil(columns),
+            tile_width,
+            tile_height,
+            duration,
+        })
+    }
+
+    fn interval(&self) -> f64 {
+        self.duration / f64::from(self.frames)
+    }
+}
+
+fn vtt_timestamp(seconds: f64) -> String {
+    let millis = (seconds * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
+
+/// One cue per frame, pointing at its tile with a `#xywh=` media fragment as
+/// hover-scrub players expect.
+fn render_vtt(layout: &SpriteLayout, image_url: &str) -> String {
+    let mut vtt = String::from("WEBVTT\n\n");
+    let interval = layout.interval();
+    for frame in 0..layout.frames {
+        let start = interval * f64::from(frame);
+        let end = (start + interval).min(layout.duration);
+        let x = frame % layout.columns * layout.tile_width;
+        let y = frame / layout.columns * layout.tile_height;
+        let _ = writeln!(vtt, "{} --> {}", vtt_timestamp(start), vtt_timestamp(end));
+        let _ = writeln!(
+            vtt,
+            "{image_url}#xywh={x},{y},{},{}\n",
+            layout.tile_width, layout.tile_height
+        );
+    }
+    vtt
+}
+
+fn sprite_image_url(base_path: &str, key: &str, layout: &SpriteLayout) -> String {
+    format!(
+        "{base_path}/api/videos/sprite/{}?format=jpg&frames={}&width={}",
+        urlencoding::encode(key),
+        layout.frames,
+        layout.tile_width
+    )
+}
+
+fn run_ffmpeg(ffmpeg: &str, args: &[&str], output: &FsPath) -> io::Result<bool> {
+    run_ffmpeg_job(
+        Command::new(ffmpeg)
+            .args(["-nostdin", "-loglevel", "error", "-y"])
+            .args(["-rw_timeout", TOOL_RW_TIMEOUT_MICROS])
+            .args(args),
+        output,
+    )
+}
+
+/// Grabs each frame with its own seek, so ffmpeg fetches a few ranges per
+/// frame instead of decoding the whole video, then tiles them into `target`.
+fn build_sprite(
+    ffmpeg: &str,
+    url: &str,
+    layout: &SpriteLayout,
+    target: &FsPath,
+) -> Result<Vec<u8>, ApiError> {
+    let work_dir = target.with_extension(format!("{}.frames", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&work_dir)
+        .map_err(|err| ApiError::internal(format!("Failed to create sprite dir: {err}")))?;
+    let result = build_sprite_in(ffmpeg, url, layout, target, &work_dir);
+    let _ = std::fs::remove_dir_all(&work_dir);
+    result
+}
+
+fn build_sprite_in(
+    ffmpeg: &str,
+    url: &str,
+    layout: &SpriteLayout,
+    target: &FsPath,
+    work_dir: &FsPath,
+) -> Result<Vec<u8>, ApiError> {
+    let ffmpeg_error = |err: io::Error| {
+        if err.kind() == io::ErrorKind::NotFound {
+            ApiError::new(
+                StatusCode::NOT_IMPLEMENTED,
+                "not_implemented",
+                "Sprites require ffmpeg, which is not installed",
+            )
+        } else {
+            tool_error(err, "ffmpeg took too long to build the sprite sheet")
+        }
+    };
+    let scale = format!("scale={}:{}", layout.tile_width, layout.tile_height);
+    let interval = layout.interval();
+    let deadline = Instant::now() + SPRITE_TIMEOUT;
+
+    for frame in 0..layout.frames {
+        if Instant::now() >= deadline {
+            return Err(ffmpeg_error(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "sprite timed out",
+            )));
+        }
+        let seek = format!("{:.3}", interval * (f64::from(frame) + 0.5));
+        let output = work_dir.join(format!("frame{frame:03}.jpg"));
+        let args = ["-ss", &seek, "-i", url, "-frames:v", "1", "-vf", &scale, "-f", "image2"];
+        if run_ffmpeg(ffmpeg, &args, &output).map_err(ffmpeg_error)? {
+            continue;
+        }
+        // A frame ffmpeg can't decode repeats its neighbour so the grid stays full.
+        let previous = work_dir.join(format!("frame{:03}.jpg", frame.saturating_sub(1)));
+        if frame == 0 || std::fs::copy(&previous, &output).is_err() {
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "sprite_failed",
+                "ffmpeg could not extract frames from this video",
+            ));
+        }
+    }
+
+    let pattern = work_dir.join("frame%03d.jpg");
+    let pattern = pattern.to_string_lossy();
+    let tile = format!("tile={}x{}", layout.columns, layout.rows);
+    let partial = partial_path(target);
+    let args = ["-i", &pattern, "-vf", &tile, "-frames:v", "1", "-f", "image2"];
+    match run_ffmpeg(ffmpeg, &args, &partial) {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(ApiError::internal("ffmpeg could not assemble the sprite sheet"));
+        }
+        Err(err) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(ffmpeg_error(err));
+        }
+    }
+
+    std::fs::rename(&partial, target)
+        .and_then(|_| std::fs::read(target))
+        .map_err(|err| ApiError::internal(format!("Failed to store sprite: {err}")))
+}
+
+/// Serves a WebVTT file mapping time ranges to tiles of a sprite sheet, or
+/// with `format=jpg` the sheet itself. Both are cached by ETag next to the
+/// thumbnails.
+#[get("/videos/sprite/{key:.*}")]
+pub async fn video_sprite(
+    state: Data<AppState>,
+    path: Path<String>,
+    query: Query<SpriteQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let key = decode_key(&path.into_inner(), &state.delimiter)?;
+    let want_image = match query.format.as_deref() {
+        None | Some("vtt") => false,
+        Some("jpg") => true,
+        Some(_) => return Err(ApiError::bad_request("format must be vtt or jpg")),
+    };
+    let frames = query.frames.unwrap_or(DEFAULT_FRAMES).clamp(1, MAX_FRAMES);
+    let tile_width = query
+        .width
+        .unwrap_or(DEFAULT_TILE_WIDTH)
+        .clamp(MIN_TILE_WIDTH, MAX_TILE_WIDTH)
+        & !1;
+
+    let head = head_object(&state, &key).await?;
+    let media = probe_media(&state, &key, head.e_tag(), head.content_type()).await;
+    let layout = SpriteLayout::new(frames, tile_width, &media).ok_or_else(|| {
+        ApiError::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "sprite_failed",
+            "Sprites need the video's duration, which ffprobe could not read",
+        )
+    })?;
+
+    let stem = format!(
+        "{}-sprite-{frames}x{tile_width}",
+        cache_stem(&key, head.e_tag())
+    );
+    let image_path = state.thumbnail_dir.join(format!("{stem}.jpg"));
+
+    if !want_image {
+        let vtt_path = state.thumbnail_dir.join(format!("{stem}.vtt"));
+        let vtt = match std::fs::read_to_string(&vtt_path) {
+            Ok(vtt) => vtt,
+            Err(_) => {
+                let vtt = render_vtt(&layout, &sprite_image_url(&state.base_path, &key, &layout));
+                // The cue file is cheap to rebuild, so a failed write is not an error.
+                let _ = std::fs::create_dir_all(&state.thumbnail_dir)
+                    .and_then(|_| std::fs::write(&vtt_path, &vtt));
+                vtt
+            }
+        };
+        return Ok(HttpResponse::Ok()
+            .insert_header((header::CONTENT_TYPE, "text/vtt; charset=utf-8"))
+            .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+            .body(vtt));
+    }
+
+    if let Ok(bytes) = std::fs::read(&image_path) {
+        return Ok(jpeg_response(bytes));
+    }
+
+    // One permit covers the whole sheet: its frames are grabbed one by one.
+    let _permit = state
+        .ffmpeg_permits
+        .acquire()
+        .await
+        .map_err(|err| ApiError::internal(format!("ffmpeg queue closed: {err}")))?;
+    if let Ok(bytes) = std::fs::read(&image_path) {
+        return Ok(jpeg_response(bytes));
+    }
+
+    let url = presigned_tool_url(&state, &key).await?;
+    let ffmpeg = state.ffmpeg_path.clone();
+    let thumbnail_dir = state.thumbnail_dir.clone();
+    let bytes = web::block(move || {
+        std::fs::create_dir_all(&thumbnail_dir)
+            .map_err(|err| ApiError::internal(format!("Failed to create thumbnail dir: {err}")))?;
+        build_sprite(&ffmpeg, &url, &layout, &image_path)
+    })
+    .await
+    .map_err(|err| ApiError::internal(format!("Sprite task failed: {err}")))??;
+
+    Ok(jpeg_response(bytes))
+}
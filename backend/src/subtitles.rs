@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    web::{Data, Path, Query},
+    HttpResponse,
+};
+use aws_sdk_s3::types::Object;
+use serde::{Deserialize, Serialize};
+
+use crate::{decode_key, error::ApiError, AppState, VideoItem};
+
+const SUBTITLE_EXTENSIONS: [&str; 2] = [".vtt", ".srt"];
+
+/// Largest subtitle file served. Real ones are tens of kilobytes; the cap
+/// keeps a huge object with a subtitle extension from being buffered whole.
+const MAX_SUBTITLE_BYTES: usize = 4 * 1024 * 1024;
+
+#[derive(Clone, Serialize)]
+pub struct SubtitleTrack {
+    /// Language suffix from names like `clip.en.vtt`, absent for `clip.vtt`.
+    pub lang: Option<String>,
+    pub url: String,
+}
+
+#[derive(Deserialize)]
+pub struct SubtitleQuery {
+    bucket: Option<String>,
+}
+
+fn subtitle_url(base_path: &str, bucket: Option<&str>, key: &str) -> String {
+    let url = format!("{base_path}/api/videos/subtitle/{}", urlencoding::encode(key));
+    match bucket {
+        Some(bucket) => format!("{url}?bucket={}", urlencoding::encode(bucket)),
+        None => url,
+    }
+}
+
+fn strip_subtitle_extension(key: &str) -> Option<&str> {
+    let lower = key.to_lowercase();
+    SUBTITLE_EXTENSIONS
+        .iter()
+        .find(|ext| lower.ends_with(*ext))
+        .map(|ext| &key[..key.len() - ext.len()])
+}
+
+/// Accepts tags like `en`, `pt-BR` or `zho`; anything else is part of the name.
+fn looks_like_lang(tag: &str) -> bool {
+    let mut parts = tag.split('-');
+    let primary = parts.next().unwrap_or_default();
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && parts.all(|part| {
+            (2..=4).contains(&part.len()) && part.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// The key of a video without its extension, which sidecar files share.
+/// Only a dot after the last `delimiter` starts an extension.
+fn video_stem<'a>(key: &'a str, delimiter: &str) -> &'a str {
+    let name_start = key
+        .rfind(delimiter)
+        .map_or(0, |index| index + delimiter.len());
+    match key[name_start..].rfind('.') {
+        Some(dot) => &key[..name_start + dot],
+        None => key,
+    }
+}
+
+/// Groups subtitle files in `objects` by the video stem they belong to.
+/// `clip.en.vtt` is filed under `clip` with language `en`, and also under
+/// `clip.en` without one in case the video itself is named `clip.en.mp4`.
+fn index_subtitles(
+    state: &AppState,
+    objects: &[Object],
+    link_bucket: Option<&str>,
+) -> HashMap<String, Vec<SubtitleTrack>> {
+    let mut index: HashMap<String, Vec<SubtitleTrack>> = HashMap::new();
+    for key in objects.iter().filter_map(|item| item.key()) {
+        let key = state.client_key(key);
+        let Some(stem) = strip_subtitle_extension(key) else {
+            continue;
+        };
+        let url = subtitle_url(&state.base_path, link_bucket, key);
+
+        if let Some((base, tag)) = stem.rsplit_once('.')
+            && !base.is_empty()
+            && !base.ends_with(state.delimiter.as_str())
+            && looks_like_lang(tag)
+        {
+            index.entry(base.to_string()).or_default().push(SubtitleTrack {
+                lang: Some(tag.to_string()),
+                url: url.clone(),
+            });
+        }
+        index
+            .entry(stem.to_string())
+            .or_default()
+            .push(SubtitleTrack { lang: None, url });
+    }
+    index
+}
+
+/// Converts SubRip to WebVTT: adds the header, normalises line endings and
+/// switches timestamp decimals from commas to dots. SRT cue numbers are kept
+/// since WebVTT accepts them as cue identifiers.
+fn srt_to_vtt(srt: &str) -> String {
+    let srt = srt.trim_start_matches('\u{feff}');
+    let mut vtt = String::with_capacity(srt.len() + 8);
+    vtt.push_str("WEBVTT\n\n");
+    for line in srt.lines() {
+        if line.contains("-->") {
+            vtt.push_str(&line.replace(',', "."));
+        } else {
+            vtt.push_str(line);
+        }
+        vtt.push('\n');
+    }
+    vtt
+}
+
+fn subtitle_too_large() -> ApiError {
+    ApiError::new(
+        StatusCode::BAD_GATEWAY,
+        "subtitle_too_large",
+        format!(
+            "Subtitle files over {} MiB are not served",
+            MAX_SUBTITLE_BYTES / 1024 / 1024
+        ),
+    )
+}
+
+/// Fills in `subtitles` for each video from sidecar files in `objects`.
+pub fn attach_subtitles(
+    state: &AppState,
+    videos: &mut [VideoItem],
+    objects: &[Object],
+    link_bucket: Option<&str>,
+) {
+    let index = index_subtitles(state, objects, link_bucket);
+    if index.is_empty() {
+        return;
+    }
+    for video in videos {
+        if let Some(tracks) = index.get(video_stem(&video.key, &state.delimiter)) {
+            video.subtitles = tracks.clone();
+        }
+    }
+}
+
+#[get("/videos/subtitle/{key:.*}")]
+pub async fn video_subtitle(
+    state: Data<AppState>,
+    path: Path<String>,
+    query: Query<SubtitleQuery>,
+) -> Result<HttpResponse, ApiError> {
+    let key = decode_key(&path.into_inner(), &state.delimiter)?;
+    let bucket = state.resolve_bucket(query.bucket.as_deref())?;
+    if strip_subtitle_extension(&key).is_none() {
+        return Err(ApiError::bad_request("Only .vtt and .srt subtitles are supported"));
+    }
+
+    let s3_key = state.s3_key(&key);
+    let get = state.retry.run("get_object", || {
+        state
+            .s3
+            .get_object()
+            .bucket(bucket)
+            .key(&s3_key)
+            .set_request_payer(state.request_payer.clone())
+            .send()
+    });
+    let object = state
+        .time_s3("get_object", bucket, &s3_key, get)
+        .await
+        .map_err(|err| {
+            state.metrics.record_s3_failure("get_object");
+            ApiError::from_s3("Failed to fetch subtitle", err)
+        })?;
+    if object
+        .content_length()
+        .is_some_and(|length| length > MAX_SUBTITLE_BYTES as i64)
+    {
+        return Err(subtitle_too_large());
+    }
+    // The length is checked again while reading, in case S3 didn't send one.
+    let mut body = object.body;
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body
+        .try_next()
+        .await
+        .map_err(|err| ApiError::internal(format!("Failed to read subtitle: {err}")))?
+    {
+        if bytes.len() + chunk.len() > MAX_SUBTITLE_BYTES {
+            return Err(subtitle_too_large());
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    // `<track>` only understands WebVTT, so SubRip is converted on the way out.
+    let body = if key.to_lowercase().ends_with(".srt") {
+        srt_to_vtt(&String::from_utf8_lossy(&bytes)).into_bytes()
+    } else {
+        bytes
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "text/vtt; charset=utf-8"))
+        .body(body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srt_to_vtt_converts_a_crlf_file_with_a_bom() {
+        let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\nHello, world\r\n\r\n\
+                   2\r\n00:01:00,250 --> 00:01:03,000\r\nSecond line\r\nwraps here\r\n";
+        assert_eq!(
+            srt_to_vtt(srt),
+            "WEBVTT\n\n\
+             1\n00:00:01.000 --> 00:00:02.500\nHello, world\n\n\
+             2\n00:01:00.250 --> 00:01:03.000\nSecond line\nwraps here\n"
+        );
+    }
+
+    #[test]
+    fn subtitle_language_comes_from_the_suffix() {
+        assert!(looks_like_lang("en"));
+        assert!(looks_like_lang("pt-BR"));
+        assert!(!looks_like_lang("director"));
+        assert_eq!(strip_subtitle_extension("clip.en.SRT"), Some("clip.en"));
+        assert_eq!(strip_subtitle_extension("clip.mp4"), None);
+    }
+
+    #[test]
+    fn video_stem_only_strips_the_extension_of_the_last_segment() {
+        assert_eq!(video_stem("a/b/clip.mp4", "/"), "a/b/clip");
+        assert_eq!(video_stem("shows:s01.x:e01.mp4", ":"), "shows:s01.x:e01");
+        assert_eq!(video_stem("shows:s01.x:e01", ":"), "shows:s01.x:e01");
+    }
+}
@@ -0,0 +1,49 @@
+use std::{pin::Pin, time::Duration};
+
+use actix_web::{
+    rt::time::{sleep_until, Instant},
+    web::Bytes,
+};
+use futures_util::{stream, Stream, StreamExt};
+
+struct Pacer<S> {
+    inner: Pin<Box<S>>,
+    pending: Bytes,
+    started: Instant,
+    sent: u64,
+    bytes_per_sec: u64,
+    slice_len: usize,
+}
+
+/// Paces `inner` to roughly `bytes_per_sec`, splitting large chunks so the
+/// rate stays smooth. Everything lives inside the returned stream, so a
+/// client disconnect drops it without leaving anything running.
+pub fn throttle<S, E>(inner: S, bytes_per_sec: u64) -> impl Stream<Item = Result<Bytes, E>>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    let pacer = Pacer {
+        inner: Box::pin(inner),
+        pending: Bytes::new(),
+        started: Instant::now(),
+        sent: 0,
+        bytes_per_sec,
+        // A tenth of a second's worth per chunk keeps bursts short.
+        slice_len: usize::try_from(bytes_per_sec / 10).unwrap_or(usize::MAX).max(1),
+    };
+
+    stream::unfold(pacer, |mut pacer| async move {
+        while pacer.pending.is_empty() {
+            match pacer.inner.next().await? {
+                Ok(chunk) => pacer.pending = chunk,
+                Err(err) => return Some((Err(err), pacer)),
+            }
+        }
+
+        let piece = pacer.pending.split_to(pacer.slice_len.min(pacer.pending.len()));
+        pacer.sent += piece.len() as u64;
+        let due = Duration::from_secs_f64(pacer.sent as f64 / pacer.bytes_per_sec as f64);
+        sleep_until(pacer.started + due).await;
+        Some((Ok(piece), pacer))
+    })
+}
@@ -0,0 +1,231 @@
+use std::{
+    io,
+    path::{Path as FsPath, PathBuf},
+    process::{Child, Command, ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
+
+use actix_web::{
+    get,
+    http::{header, StatusCode},
+    web::{self, Data, Path},
+    HttpResponse,
+};
+use aws_sdk_s3::presigning::PresigningConfig;
+
+use crate::{decode_key, error::ApiError, head_object, object_id, AppState};
+
+/// ffmpeg and ffprobe only need the presigned URL for as long as one run takes.
+const FFMPEG_URL_EXPIRY: Duration = Duration::from_secs(300);
+
+/// ffmpeg's and ffprobe's own limit on a single read, in microseconds, so a
+/// stalled connection to S3 errors out instead of hanging.
+pub const TOOL_RW_TIMEOUT_MICROS: &str = "10000000";
+
+/// Hard limit on one ffmpeg run, for when it keeps making slow progress.
+const FFMPEG_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How often `wait_with_deadline` checks whether the tool has exited.
+const TOOL_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Frames are taken a second in so fade-ins don't produce black posters.
+const SEEK_SECONDS: &str = "1";
+
+/// A file-name-safe form of the object's ID, so re-uploads get new cache
+/// files.
+pub fn cache_stem(key: &str, e_tag: Option<&str>) -> String {
+    object_id(key, e_tag)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+fn cache_file_name(key: &str, e_tag: Option<&str>) -> String {
+    format!("{}.jpg", cache_stem(key, e_tag))
+}
+
+/// Presigns a short-lived GET so ffmpeg and ffprobe can read the object
+/// directly, seeking with ranged requests instead of downloading all of it.
+pub async fn presigned_tool_url(state: &AppState, key: &str) -> Result<String, ApiError> {
+    let presign_config = PresigningConfig::expires_in(FFMPEG_URL_EXPIRY)
+        .map_err(|err| ApiError::internal(format!("Invalid presign expiry: {err}")))?;
+    let s3_key = state.s3_key(key);
+    let presign = state
+        .s3
+        .get_object()
+        .bucket(&state.bucket)
+        .key(&s3_key)
+        .set_request_payer(state.request_payer.clone())
+        .presigned(presign_config);
+    let presigned = state
+        .time_s3("presign_get_object", &state.bucket, &s3_key, presign)
+        .await
+        .map_err(|err| {
+            state.metrics.record_s3_failure("presign_get_object");
+            ApiError::from_s3("Failed to presign URL", err)
+        })?;
+    Ok(presigned.uri().to_string())
+}
+
+/// Waits for `child`, killing it once `timeout` has passed so a stalled read
+/// from S3 can't hold a blocking thread forever. `None` means it was killed.
+pub fn wait_with_deadline(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            child.wait()?;
+            return Ok(None);
+        }
+        std::thread::sleep(TOOL_POLL_INTERVAL);
+    }
+}
+
+/// Runs one ffmpeg job writing `output`, killing it after `FFMPEG_TIMEOUT`
+/// and reporting that as `TimedOut`.
+pub fn run_ffmpeg_job(command: &mut Command, output: &FsPath) -> io::Result<bool> {
+    let mut child = command
+        .arg(output)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    match wait_with_deadline(&mut child, FFMPEG_TIMEOUT)? {
+        Some(status) => Ok(status.success() && output.exists()),
+        None => Err(io::Error::new(io::ErrorKind::TimedOut, "ffmpeg timed out")),
+    }
+}
+
+/// A temporary name next to `target`, unique per request so concurrent jobs
+/// for the same file never write over each other.
+pub fn partial_path(target: &FsPath) -> PathBuf {
+    target.with_extension(format!("jpg.{}.partial", uuid::Uuid::new_v4()))
+}
+
+fn run_ffmpeg(ffmpeg: &str, url: &str, seek: &str, output: &FsPath) -> io::Result<bool> {
+    run_ffmpeg_job(
+        Command::new(ffmpeg)
+            .args(["-nostdin", "-loglevel", "error", "-y"])
+            .args(["-rw_timeout", TOOL_RW_TIMEOUT_MICROS, "-ss", seek, "-i", url])
+            .args(["-frames:v", "1", "-vf", "scale=480:-2", "-f", "image2"]),
+        output,
+    )
+}
+
+/// Extracts a frame into `target`, writing to a temporary file first so a
+/// concurrent request never serves a half-written JPEG.
+fn extract_frame(ffmpeg: &str, url: &str, target: &FsPath) -> Result<Vec<u8>, ApiError> {
+    let partial = partial_path(target);
+    let extracted = match run_ffmpeg(ffmpeg, url, SEEK_SECONDS, &partial) {
+        // Clips shorter than the seek offset yield nothing, so retry from the start.
+        Ok(false) => run_ffmpeg(ffmpeg, url, "0", &partial),
+        other => other,
+    };
+
+    match extracted {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "thumbnail_failed",
+                "ffmpeg could not extract a frame from this video",
+            ));
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(ApiError::new(
+                StatusCode::NOT_IMPLEMENTED,
+                "not_implemented",
+                "Thumbnails require ffmpeg, which is not installed",
+            ));
+        }
+        Err(err) => {
+            let _ = std::fs::remove_file(&partial);
+            return Err(tool_error(err, "ffmpeg took too long to extract a frame"));
+        }
+    }
+
+    std::fs::rename(&partial, target)
+        .and_then(|_| std::fs::read(target))
+        .map_err(|err| ApiError::internal(format!("Failed to store thumbnail: {err}")))
+}
+
+/// 504 for a run killed at its deadline, 500 for anything else.
+pub fn tool_error(err: io::Error, timeout_message: &str) -> ApiError {
+    if err.kind() == io::ErrorKind::TimedOut {
+        ApiError::new(StatusCode::GATEWAY_TIMEOUT, "tool_timeout", timeout_message)
+    } else {
+        ApiError::internal(format!("Failed to run ffmpeg: {err}"))
+    }
+}
+
+pub fn jpeg_response(bytes: Vec<u8>) -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, "image/jpeg"))
+        .insert_header((header::CACHE_CONTROL, "public, max-age=86400"))
+        .body(bytes)
+}
+
+#[get("/videos/thumbnail/{key:.*}")]
+pub async fn video_thumbnail(
+    state: Data<AppState>,
+    path: Path<String>,
+) -> Result<HttpResponse, ApiError> {
+    let key = decode_key(&path.into_inner(), &state.delimiter)?;
+
+    let head = head_object(&state, &key).await?;
+
+    let cache_path: PathBuf = state
+        .thumbnail_dir
+        .join(cache_file_name(&key, head.e_tag()));
+    if let Ok(bytes) = std::fs::read(&cache_path) {
+        return Ok(jpeg_response(bytes));
+    }
+
+    let _permit = state
+        .ffmpeg_permits
+        .acquire()
+        .await
+        .map_err(|err| ApiError::internal(format!("ffmpeg queue closed: {err}")))?;
+    // Another request may have made it while this one waited its turn.
+    if let Ok(bytes) = std::fs::read(&cache_path) {
+        return Ok(jpeg_response(bytes));
+    }
+
+    let url = presigned_tool_url(&state, &key).await?;
+    let ffmpeg = state.ffmpeg_path.clone();
+    let thumbnail_dir = state.thumbnail_dir.clone();
+    let bytes = web::block(move || {
+        std::fs::create_dir_all(&thumbnail_dir)
+            .map_err(|err| ApiError::internal(format!("Failed to create thumbnail dir: {err}")))?;
+        extract_frame(&ffmpeg, &url, &cache_path)
+    })
+    .await
+    .map_err(|err| ApiError::internal(format!("Thumbnail task failed: {err}")))??;
+
+    Ok(jpeg_response(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wait_with_deadline_kills_a_stalled_tool() {
+        let mut child = Command::new("sleep").arg("10").spawn().unwrap();
+        let started = Instant::now();
+        let status = wait_with_deadline(&mut child, Duration::from_millis(100)).unwrap();
+        assert!(status.is_none());
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn wait_with_deadline_returns_the_exit_status() {
+        let mut child = Command::new("true").spawn().unwrap();
+        let status = wait_with_deadline(&mut child, Duration::from_secs(5)).unwrap();
+        assert!(status.is_some_and(|status| status.success()));
+    }
+}
@@ -0,0 +1,52 @@
+use std::{path::Path, sync::Arc};
+
+use anyhow::{Context, Result};
+use rustls::{
+    crypto::aws_lc_rs,
+    pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+    ServerConfig,
+};
+
+/// Certificate chain and private key locations, both PEM encoded.
+#[derive(Debug, Clone)]
+pub struct TlsPaths {
+    pub cert_file: String,
+    pub key_file: String,
+}
+
+impl TlsPaths {
+    /// TLS is only enabled when both files are configured; setting just one
+    /// is almost certainly a mistake, so it is rejected rather than ignored.
+    pub fn from_vars(cert_file: Option<String>, key_file: Option<String>) -> Result<Option<Self>> {
+        match (
+            cert_file.filter(|path| !path.is_empty()),
+            key_file.filter(|path| !path.is_empty()),
+        ) {
+            (Some(cert_file), Some(key_file)) => Ok(Some(Self { cert_file, key_file })),
+            (None, None) => Ok(None),
+            (Some(_), None) => anyhow::bail!("TLS_CERT_FILE is set but TLS_KEY_FILE is missing"),
+            (None, Some(_)) => anyhow::bail!("TLS_KEY_FILE is set but TLS_CERT_FILE is missing"),
+        }
+    }
+}
+
+/// Loads the certificate chain and key into a rustls server config so startup
+/// fails immediately on unreadable or mismatched files.
+pub fn load_server_config(paths: &TlsPaths) -> Result<ServerConfig> {
+    let cert_chain = CertificateDer::pem_file_iter(Path::new(&paths.cert_file))
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read TLS certificates from {}", paths.cert_file))?;
+    if cert_chain.is_empty() {
+        anyhow::bail!("No certificates found in {}", paths.cert_file);
+    }
+
+    let key = PrivateKeyDer::from_pem_file(Path::new(&paths.key_file))
+        .with_context(|| format!("Failed to read TLS private key from {}", paths.key_file))?;
+
+    ServerConfig::builder_with_provider(Arc::new(aws_lc_rs::default_provider()))
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS protocol versions")?
+        .with_no_client_auth()
+        .with_single_cert(cert_chain, key)
+        .context("Invalid TLS certificate or private key")
+}
@@ -0,0 +1,339 @@
+use std::time::Duration;
+
+use actix_web::{
+    post,
+    web::{self, Data},
+    HttpRequest, HttpResponse,
+};
+use aws_sdk_s3::{
+    error::ProvideErrorMetadata,
+    presigning::PresigningConfig,
+    types::{CompletedMultipartUpload, CompletedPart},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{auth, content_type_for_key, error::ApiError, stream_url, validate_key, AppState};
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub struct UploadUrlRequest {
+    key: String,
+    contentType: Option<String>,
+}
+
+#[derive(Serialize)]
+struct UploadUrlResponse {
+    key: String,
+    #[serde(rename = "uploadUrl")]
+    upload_url: String,
+    /// The PUT must send exactly this Content-Type, since it is signed in.
+    #[serde(rename = "contentType")]
+    content_type: String,
+    #[serde(rename = "expiresIn")]
+    expires_in: u64,
+    /// Where the video will stream from once the upload finishes.
+    #[serde(rename = "streamUrl")]
+    stream_url: String,
+}
+
+/// S3 numbers multipart parts from 1 to 10,000.
+const MAX_PART_NUMBER: i32 = 10_000;
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub struct MultipartUploadRef {
+    key: String,
+    uploadId: String,
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub struct SignPartRequest {
+    #[serde(flatten)]
+    upload: MultipartUploadRef,
+    partNumber: i32,
+}
+
+#[derive(Deserialize)]
+#[allow(non_snake_case)]
+pub struct UploadedPart {
+    partNumber: i32,
+    eTag: String,
+}
+
+#[derive(Deserialize)]
+pub struct CompleteRequest {
+    #[serde(flatten)]
+    upload: MultipartUploadRef,
+    parts: Vec<UploadedPart>,
+}
+
+#[derive(Serialize)]
+struct MultipartStartResponse {
+    key: String,
+    #[serde(rename = "uploadId")]
+    upload_id: String,
+}
+
+#[derive(Serialize)]
+struct SignPartResponse {
+    #[serde(rename = "partNumber")]
+    part_number: i32,
+    url: String,
+    #[serde(rename = "expiresIn")]
+    expires_in: u64,
+}
+
+#[derive(Serialize)]
+struct MultipartCompleteResponse {
+    key: String,
+    #[serde(rename = "streamUrl")]
+    stream_url: String,
+}
+
+/// Checks a key a client wants to write: the usual traversal rules plus one
+/// of the configured video extensions, so uploads always show up in listings.
+pub fn validate_upload_key(state: &AppState, key: &str) -> Result<(), ApiError> {
+    validate_key(key, &state.delimiter)?;
+    if key.is_empty() || key.ends_with('/') || key.ends_with(state.delimiter.as_str()) {
+        return Err(ApiError::bad_request("Upload key must name a file"));
+    }
+    if !state.is_video_key(key) {
+        return Err(ApiError::bad_request(format!(
+            "Upload key must end in one of {}",
+            state.video_extensions.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+pub fn presigning_config(state: &AppState) -> Result<PresigningConfig, ApiError> {
+    PresigningConfig::expires_in(Duration::from_secs(state.upload_url_expiry_seconds))
+        .map_err(|err| ApiError::internal(format!("Invalid presign expiry: {err}")))
+}
+
+/// Presigns a PUT so the browser uploads straight to S3 under `KEY_PREFIX`.
+#[post("/videos/upload-url")]
+pub async fn upload_url(
+    req: HttpRequest,
+    state: Data<AppState>,
+    body: web::Json<UploadUrlRequest>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_admin(&req)?;
+    let UploadUrlRequest { key, contentType } = body.into_inner();
+    validate_upload_key(&state, &key)?;
+    let content_type = contentType
+        .filter(|content_type| !content_type.trim().is_empty())
+        .unwrap_or_else(|| content_type_for_key(&key).to_string());
+
+    let s3_key = state.s3_key(&key);
+    let presign = state
+        .s3
+        .put_object()
+        .bucket(&state.bucket)
+        .key(&s3_key)
+        .content_type(&content_type)
+        .presigned(presigning_config(&state)?);
+    let presigned = state
+        .time_s3("presign_put_object", &state.bucket, &s3_key, presign)
+        .await
+        .map_err(|err| {
+            state.metrics.record_s3_failure("presign_put_object");
+            ApiError::from_s3("Failed to presign upload URL", err)
+        })?;
+
+    Ok(HttpResponse::Ok().json(UploadUrlResponse {
+        upload_url: presigned.uri().to_string(),
+        content_type,
+        expires_in: state.upload_url_expiry_seconds,
+        stream_url: stream_url(&state.base_path, None, &key),
+        key,
+    }))
+}
+
+/// Starts a multipart upload; parts are then signed one at a time.
+#[post("/videos/multipart/start")]
+pub async fn multipart_start(
+    req: HttpRequest,
+    state: Data<AppState>,
+    body: web::Json<UploadUrlRequest>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_admin(&req)?;
+    let UploadUrlRequest { key, contentType } = body.into_inner();
+    validate_upload_key(&state, &key)?;
+    let content_type = contentType
+        .filter(|content_type| !content_type.trim().is_empty())
+        .unwrap_or_else(|| content_type_for_key(&key).to_string());
+
+    let s3_key = state.s3_key(&key);
+    let create = state.retry.run("create_multipart_upload", || {
+        state
+            .s3
+            .create_multipart_upload()
+            .bucket(&state.bucket)
+            .key(&s3_key)
+            .content_type(&content_type)
+            .send()
+    });
+    let created = state
+        .time_s3("create_multipart_upload", &state.bucket, &s3_key, create)
+        .await
+        .map_err(|err| {
+            state.metrics.record_s3_failure("create_multipart_upload");
+            ApiError::from_s3("Failed to start multipart upload", err)
+        })?;
+    let upload_id = created
+        .upload_id()
+        .ok_or_else(|| ApiError::internal("S3 returned no upload ID"))?
+        .to_string();
+
+    Ok(HttpResponse::Ok().json(MultipartStartResponse { key, upload_id }))
+}
+
+/// Presigns the PUT for one part. The browser reads each part's `ETag`
+/// response header and sends them all back to `/complete`.
+#[post("/videos/multipart/sign")]
+pub async fn multipart_sign(
+    req: HttpRequest,
+    state: Data<AppState>,
+    body: web::Json<SignPartRequest>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_admin(&req)?;
+    let SignPartRequest { upload, partNumber } = body.into_inner();
+    validate_upload_key(&state, &upload.key)?;
+    if !(1..=MAX_PART_NUMBER).contains(&partNumber) {
+        return Err(ApiError::bad_request(format!(
+            "partNumber must be between 1 and {MAX_PART_NUMBER}"
+        )));
+    }
+
+    let s3_key = state.s3_key(&upload.key);
+    let presign = state
+        .s3
+        .upload_part()
+        .bucket(&state.bucket)
+        .key(&s3_key)
+        .upload_id(&upload.uploadId)
+        .part_number(partNumber)
+        .presigned(presigning_config(&state)?);
+    let presigned = state
+        .time_s3("presign_upload_part", &state.bucket, &s3_key, presign)
+        .await
+        .map_err(|err| {
+            state.metrics.record_s3_failure("presign_upload_part");
+            ApiError::from_s3("Failed to presign upload part", err)
+        })?;
+
+    Ok(HttpResponse::Ok().json(SignPartResponse {
+        part_number: partNumber,
+        url: presigned.uri().to_string(),
+        expires_in: state.upload_url_expiry_seconds,
+    }))
+}
+
+/// Abandons an upload so S3 frees the parts stored so far. Failures are only
+/// logged; a bucket lifecycle rule is the backstop for stray uploads.
+async fn abort_upload(state: &AppState, s3_key: &str, upload_id: &str) {
+    let abort = state.retry.run("abort_multipart_upload", || {
+        state
+            .s3
+            .abort_multipart_upload()
+            .bucket(&state.bucket)
+            .key(s3_key)
+            .upload_id(upload_id)
+            .send()
+    });
+    if let Err(err) = state
+        .time_s3("abort_multipart_upload", &state.bucket, s3_key, abort)
+        .await
+    {
+        state.metrics.record_s3_failure("abort_multipart_upload");
+        tracing::warn!("Failed to abort multipart upload {upload_id} for {s3_key}: {err}");
+    }
+}
+
+/// Whether S3 refused to complete an upload for a reason a retry can't fix:
+/// a part list or ETag that doesn't match what was uploaded. Anything else,
+/// timeouts and 5xx included, leaves the parts intact for another attempt.
+fn is_permanent_complete_failure(code: Option<&str>) -> bool {
+    matches!(
+        code,
+        Some("InvalidPart" | "InvalidPartOrder" | "EntityTooSmall")
+    )
+}
+
+/// Assembles the uploaded parts into the final object. If S3 rejects the
+/// part list, the upload is aborted so its parts don't linger and the client
+/// starts over; transient failures are returned so the client can retry the
+/// complete call.
+#[post("/videos/multipart/complete")]
+pub async fn multipart_complete(
+    req: HttpRequest,
+    state: Data<AppState>,
+    body: web::Json<CompleteRequest>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_admin(&req)?;
+    let CompleteRequest { upload, mut parts } = body.into_inner();
+    validate_upload_key(&state, &upload.key)?;
+    if parts.is_empty() {
+        return Err(ApiError::bad_request("parts must not be empty"));
+    }
+    parts.sort_by_key(|part| part.partNumber);
+
+    let completed = CompletedMultipartUpload::builder()
+        .set_parts(Some(
+            parts
+                .into_iter()
+                .map(|part| {
+                    CompletedPart::builder()
+                        .part_number(part.partNumber)
+                        .e_tag(part.eTag)
+                        .build()
+                })
+                .collect(),
+        ))
+        .build();
+
+    let s3_key = state.s3_key(&upload.key);
+    let complete = state.retry.run("complete_multipart_upload", || {
+        state
+            .s3
+            .complete_multipart_upload()
+            .bucket(&state.bucket)
+            .key(&s3_key)
+            .upload_id(&upload.uploadId)
+            .multipart_upload(completed.clone())
+            .send()
+    });
+    let result = state
+        .time_s3("complete_multipart_upload", &state.bucket, &s3_key, complete)
+        .await;
+    if let Err(err) = result {
+        state.metrics.record_s3_failure("complete_multipart_upload");
+        // `NoSuchUpload` can't succeed either, but there is nothing to abort.
+        if is_permanent_complete_failure(err.code()) {
+            abort_upload(&state, &s3_key, &upload.uploadId).await;
+        }
+        return Err(ApiError::from_s3("Failed to complete multipart upload", err));
+    }
+
+    state.invalidate_listings(&state.bucket, &upload.key);
+    Ok(HttpResponse::Ok().json(MultipartCompleteResponse {
+        stream_url: stream_url(&state.base_path, None, &upload.key),
+        key: upload.key,
+    }))
+}
+
+/// Lets a client give up on an upload, e.g. after a part keeps failing.
+#[post("/videos/multipart/abort")]
+pub async fn multipart_abort(
+    req: HttpRequest,
+    state: Data<AppState>,
+    body: web::Json<MultipartUploadRef>,
+) -> Result<HttpResponse, ApiError> {
+    auth::require_admin(&req)?;
+    let upload = body.into_inner();
+    validate_upload_key(&state, &upload.key)?;
+    abort_upload(&state, &state.s3_key(&upload.key), &upload.uploadId).await;
+    Ok(HttpResponse::NoContent().finish())
+}
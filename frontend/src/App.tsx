@@ -13,11 +13,16 @@ const DEFAULT_TITLE = "S3 Video Streaming";
 
 export default function App() {
   const { page, setPage, prefix, setPrefix } = useUrlState();
-  const { crumbs, getBackPrefix } = useBreadcrumbs(prefix);
-  const { loading, error, videos, folders, pagination } = useVideos(
-    page,
-    prefix,
-  );
+  const {
+    loading,
+    error,
+    videos,
+    folders,
+    breadcrumbs,
+    pagination,
+    truncated,
+  } = useVideos(page, prefix);
+  const { crumbs, getBackPrefix } = useBreadcrumbs(breadcrumbs);
   const dialog = useVideoDialog(DEFAULT_TITLE);
 
   const handleFolderClick = (folderPrefix: string) => {
@@ -81,6 +86,13 @@ export default function App() {
             </div>
           )}
 
+          {!loading() && truncated() && (
+            <div class="mt-8 rounded-2xl border border-amber-200 bg-amber-50 px-4 py-3 text-center text-sm text-amber-700">
+              This folder has too many objects to list in full; only some
+              videos are shown.
+            </div>
+          )}
+
           {!loading() && (
             <div class="mt-6 flex flex-col gap-8">
               <FolderGrid folders={folders()} onSelect={handleFolderClick} />
@@ -1,7 +1,8 @@
 import { For, Show } from "solid-js";
+import type { FolderItem } from "../types/api";
 
 type Props = {
-  folders: string[];
+  folders: FolderItem[];
   onSelect: (prefix: string) => void;
 };
 
@@ -15,18 +16,26 @@ export default function FolderGrid(props: Props) {
         <div class="grid grid-cols-1 gap-3 sm:grid-cols-2 lg:grid-cols-3">
           <For each={props.folders}>
             {(folder) => {
-              const parts = folder.split("/").filter(Boolean);
-              const name = parts[parts.length - 1] ?? folder;
+              const name = folder.name || folder.prefix;
               return (
                 <button
                   type="button"
-                  onClick={() => props.onSelect(folder)}
+                  onClick={() => props.onSelect(folder.prefix)}
                   class="group flex flex-col gap-2 rounded-2xl border border-blue-100 bg-blue-50/70 p-4 text-left transition hover:-translate-y-1 hover:bg-white hover:shadow-card"
                 >
                   <span class="text-sm font-semibold text-blue-900">
                     {name}
                   </span>
-                  <span class="text-xs text-blue-700/70">{folder}</span>
+                  <span class="text-xs text-blue-700/70">
+                    {folder.prefix}
+                    {folder.videoCount === null
+                      ? ""
+                      : folder.videoCountTruncated
+                        ? ` · ${folder.videoCount}+ videos`
+                        : folder.videoCount === 1
+                          ? " · 1 video"
+                          : ` · ${folder.videoCount} videos`}
+                  </span>
                 </button>
               );
             }}
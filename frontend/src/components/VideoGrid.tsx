@@ -24,38 +24,35 @@ export default function VideoGrid(props: Props) {
       >
         <div class="grid grid-cols-1 gap-5 sm:grid-cols-2">
           <For each={props.videos}>
-            {(video) => {
-              const fileName = video.key.split("/").pop() ?? video.key;
-              return (
-                <button
-                  type="button"
-                  onClick={() => props.onPlay(video.streamUrl, fileName)}
-                  class="group flex h-full flex-col gap-3 rounded-2xl border border-white bg-white p-5 text-left shadow-card transition hover:-translate-y-1 hover:shadow-xl"
-                >
-                  <div class="flex items-center justify-between gap-4">
-                    <div class="flex-1">
-                      <div class="text-sm font-semibold text-slate-900">
-                        {fileName}
-                      </div>
-                      <div class="mt-1 text-xs text-slate-500 break-all">
-                        {video.key}
-                      </div>
+            {(video) => (
+              <button
+                type="button"
+                onClick={() => props.onPlay(video.streamUrl, video.title)}
+                class="group flex h-full flex-col gap-3 rounded-2xl border border-white bg-white p-5 text-left shadow-card transition hover:-translate-y-1 hover:shadow-xl"
+              >
+                <div class="flex items-center justify-between gap-4">
+                  <div class="flex-1">
+                    <div class="text-sm font-semibold text-slate-900">
+                      {video.title}
                     </div>
-                    <div class="rounded-full bg-slate-900 px-3 py-1 text-xs font-semibold text-white">
-                      Play
+                    <div class="mt-1 text-xs text-slate-500 break-all">
+                      {video.key}
                     </div>
                   </div>
-                  <div class="flex flex-wrap gap-3 text-xs text-slate-500">
-                    <span class="rounded-full bg-slate-100 px-3 py-1">
-                      {props.formatSize(video.size)}
-                    </span>
-                    <span class="rounded-full bg-slate-100 px-3 py-1">
-                      Modified: {props.formatDate(video.lastModified)}
-                    </span>
+                  <div class="rounded-full bg-slate-900 px-3 py-1 text-xs font-semibold text-white">
+                    Play
                   </div>
-                </button>
-              );
-            }}
+                </div>
+                <div class="flex flex-wrap gap-3 text-xs text-slate-500">
+                  <span class="rounded-full bg-slate-100 px-3 py-1">
+                    {props.formatSize(video.size)}
+                  </span>
+                  <span class="rounded-full bg-slate-100 px-3 py-1">
+                    Modified: {props.formatDate(video.lastModified)}
+                  </span>
+                </div>
+              </button>
+            )}
           </For>
         </div>
       </Show>
@@ -1,33 +1,23 @@
 import { createMemo, type Accessor } from "solid-js";
+import type { Breadcrumb } from "../types/api";
 
 type Crumb = {
   label: string;
   prefix: string;
 };
 
-export default function useBreadcrumbs(prefix: Accessor<string>) {
-  const parts = createMemo(() => {
-    const current = prefix();
-    if (!current) return [] as string[];
-    return current.split("/").filter(Boolean);
-  });
-
-  const crumbs = createMemo<Crumb[]>(() => {
-    const list = parts();
-    if (list.length === 0) return [{ label: "Root", prefix: "" }];
-    const result: Crumb[] = [{ label: "Root", prefix: "" }];
-    let acc = "";
-    for (const part of list) {
-      acc += `${part}/`;
-      result.push({ label: part, prefix: acc });
-    }
-    return result;
-  });
+export default function useBreadcrumbs(breadcrumbs: Accessor<Breadcrumb[]>) {
+  const crumbs = createMemo<Crumb[]>(() => [
+    { label: "Root", prefix: "" },
+    ...breadcrumbs().map((crumb) => ({
+      label: crumb.name,
+      prefix: crumb.prefix,
+    })),
+  ]);
 
   const getBackPrefix = () => {
-    const list = parts();
-    const next = list.slice(0, -1);
-    return next.length ? `${next.join("/")}/` : "";
+    const list = breadcrumbs();
+    return list.length > 1 ? list[list.length - 2].prefix : "";
   };
 
   return { crumbs, getBackPrefix };
@@ -1,5 +1,11 @@
 import { createEffect, createSignal, type Accessor } from "solid-js";
-import type { ListResponse, Pagination, VideoItem } from "../types/api";
+import type {
+  Breadcrumb,
+  FolderItem,
+  ListResponse,
+  Pagination,
+  VideoItem,
+} from "../types/api";
 
 export default function useVideos(
   page: Accessor<number>,
@@ -8,8 +14,10 @@ export default function useVideos(
   const [loading, setLoading] = createSignal(true);
   const [error, setError] = createSignal<string | null>(null);
   const [videos, setVideos] = createSignal<VideoItem[]>([]);
-  const [folders, setFolders] = createSignal<string[]>([]);
+  const [folders, setFolders] = createSignal<FolderItem[]>([]);
+  const [breadcrumbs, setBreadcrumbs] = createSignal<Breadcrumb[]>([]);
   const [pagination, setPagination] = createSignal<Pagination | null>(null);
+  const [truncated, setTruncated] = createSignal(false);
 
   let activeRequest = 0;
 
@@ -30,7 +38,9 @@ export default function useVideos(
 
       setVideos(data.videos || []);
       setFolders(data.folders || []);
-      setPagination(data.pagination);
+      setBreadcrumbs(data.breadcrumbs || []);
+      setPagination(data.pagination ?? null);
+      setTruncated(data.truncated ?? false);
       setLoading(false);
     } catch (err) {
       if (requestId !== activeRequest) return;
@@ -49,7 +59,9 @@ export default function useVideos(
     error,
     videos,
     folders,
+    breadcrumbs,
     pagination,
+    truncated,
     refetch: fetchVideos,
   };
 }
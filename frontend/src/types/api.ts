@@ -1,8 +1,19 @@
+export type SubtitleTrack = {
+  lang?: string | null;
+  url: string;
+};
+
 export type VideoItem = {
+  id: string;
   key: string;
+  title: string;
   size: number;
   lastModified?: string | null;
   streamUrl: string;
+  thumbnailUrl?: string;
+  storageClass?: string;
+  subtitles?: SubtitleTrack[];
+  tags?: Record<string, string>;
 };
 
 export type Pagination = {
@@ -12,11 +23,67 @@ export type Pagination = {
   totalVideos: number;
   hasNextPage: boolean;
   hasPrevPage: boolean;
+  totalBytes: number;
+  pageBytes: number;
+};
+
+export type FolderItem = {
+  prefix: string;
+  name: string;
+  videoCount: number | null;
+  videoCountTruncated?: boolean;
+};
+
+export type Breadcrumb = {
+  name: string;
+  prefix: string;
 };
 
 export type ListResponse = {
   prefix: string;
-  folders: string[];
+  breadcrumbs: Breadcrumb[];
+  folders: FolderItem[];
   videos: VideoItem[];
-  pagination: Pagination;
+  pagination?: Pagination;
+  nextContinuationToken?: string;
+  nextCursor?: string;
+  pageSize: number;
+  truncated?: boolean;
+  warnings?: string[];
+};
+
+export type ObjectListResponse = {
+  prefix: string;
+  breadcrumbs: Breadcrumb[];
+  folders: FolderItem[];
+  objects: VideoItem[];
+  pagination?: Pagination;
+  nextContinuationToken?: string;
+  nextCursor?: string;
+  pageSize: number;
+  truncated?: boolean;
+  warnings?: string[];
+};
+
+export type ObjectMetadata = {
+  key: string;
+  contentType?: string | null;
+  contentLength?: number | null;
+  lastModified?: string | null;
+  eTag?: string | null;
+  metadata: Record<string, string>;
+  tags: Record<string, string>;
+  storageClass?: string | null;
+  restoreStatus: "none" | "ongoing" | "restored";
+  durationSeconds?: number;
+  width?: number;
+  height?: number;
+};
+
+export type BatchMetadataItem = ObjectMetadata | { key: string; notFound: true };
+
+export type ChangeEvent = {
+  prefix: string;
+  added: string[];
+  removed: string[];
 };